@@ -15,6 +15,8 @@ pub enum DisplayMode {
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub endpoint: Option<String>,
+    pub user: Option<String>,
+    pub token: Option<String>,
     pub limit: Option<usize>,
     pub display_mode: Option<DisplayMode>,
 }
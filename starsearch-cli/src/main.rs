@@ -4,54 +4,204 @@ mod tui;
 
 use self::models::Language;
 use crate::config::{Config, DisplayMode};
-use chrono::{DateTime, Local, TimeDelta};
-use clap::Parser;
-use console::style;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, Utc};
+use clap::{Parser, Subcommand};
+use console::{style, Style};
 use core::fmt;
 use models::LanguageMap;
 use starsearch_sdk::client::Client;
-use starsearch_sdk::models::Repository;
+use starsearch_sdk::models::{
+    DateRange, FacetCount, Facets, Filter, HighlightedText, JobState, Repository, SearchMode,
+    SearchOptions, Sort,
+};
+use starsearch_sdk::query::end_of_day;
 use std::collections::HashMap;
 use std::error::Error;
 use std::process::exit;
 
 const LANGUAGE_COLORS_ENDPOINT: &str = "https://languages.ranna.dev/languages.minified.json";
 
+/// Number of values per facet shown in the facet summary.
+const FACET_SUMMARY_VALUES: usize = 5;
+
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true
+)]
 struct Args {
-    /// The search query.
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The search query. Put it after "--" if it starts with the name of a
+    /// subcommand (e.g. "-- note taking apps").
     query: Vec<String>,
 
     /// Filter by programming language.
     #[arg(short, long, alias = "lang")]
     language: Option<String>,
 
+    /// Only show repositories having the given topic. Can be passed
+    /// multiple times to require all of the topics.
+    #[arg(short, long)]
+    topic: Vec<String>,
+
+    /// Only show repositories having any of the given topics. Can be passed
+    /// multiple times.
+    #[arg(long)]
+    topic_any: Vec<String>,
+
+    /// Filter by SPDX license identifier (e.g. "MIT").
+    #[arg(long)]
+    license: Option<String>,
+
+    /// Filter by repository owner.
+    #[arg(short, long)]
+    owner: Option<String>,
+
+    /// Only show repositories in the given star list. Can be passed multiple
+    /// times to require all of the lists.
+    #[arg(long)]
+    list: Vec<String>,
+
+    /// Only show forks (true) or non-forks (false).
+    #[arg(long)]
+    fork: Option<bool>,
+
+    /// Only show archived (true) or active (false) repositories.
+    #[arg(long)]
+    archived: Option<bool>,
+
+    /// Only show repositories created on or after the given date (YYYY-MM-DD).
+    #[arg(long)]
+    created_after: Option<NaiveDate>,
+
+    /// Only show repositories created on or before the given date (YYYY-MM-DD).
+    #[arg(long)]
+    created_before: Option<NaiveDate>,
+
+    /// Only show repositories updated on or after the given date (YYYY-MM-DD).
+    #[arg(long)]
+    updated_after: Option<NaiveDate>,
+
+    /// Only show repositories updated on or before the given date (YYYY-MM-DD).
+    #[arg(long)]
+    updated_before: Option<NaiveDate>,
+
+    /// Sort results by the date they have been starred
+    /// ("starred_at:desc" or "starred_at:asc").
+    #[arg(long)]
+    sort: Option<Sort>,
+
+    /// How the query is matched: "keyword", "semantic" or "hybrid". The
+    /// semantic modes match by meaning rather than wording, if enabled on
+    /// the server.
+    #[arg(short, long, default_value_t = SearchMode::Keyword)]
+    mode: SearchMode,
+
+    /// Only show repositories starred on or after the given date (YYYY-MM-DD).
+    #[arg(long)]
+    starred_after: Option<NaiveDate>,
+
+    /// Only show repositories starred on or before the given date (YYYY-MM-DD).
+    #[arg(long)]
+    starred_before: Option<NaiveDate>,
+
     /// Maximum number of results shown.
-    #[arg(short = 'n', long)]
+    #[arg(short = 'n', long, global = true)]
     limit: Option<usize>,
 
+    /// The page of results shown.
+    #[arg(long, default_value_t = 1)]
+    page: usize,
+
+    /// Show a summary of the most frequent languages, topics, licenses and
+    /// owners among all matching repositories.
+    #[arg(long)]
+    facets: bool,
+
     /// Display results in codensed mode.
-    #[arg(short, long, visible_short_alias = 's', visible_alias = "short")]
+    #[arg(
+        short,
+        long,
+        visible_short_alias = 's',
+        visible_alias = "short",
+        global = true
+    )]
     condensed: bool,
 
     /// Display results in detailed mode.
-    #[arg(short, long, visible_alias = "long")]
+    #[arg(short, long, visible_alias = "long", global = true)]
     detailed: bool,
 
     /// The starsearch API endpoint.
-    #[arg(short, long, env = "STARSEARCH_ENDPOINT")]
+    #[arg(short, long, env = "STARSEARCH_ENDPOINT", global = true)]
     endpoint: Option<String>,
 
+    /// The user whose stars are accessed. Defaults to the first user
+    /// configured on the server.
+    #[arg(short, long, env = "STARSEARCH_USER", global = true)]
+    user: Option<String>,
+
+    /// The API token to authenticate with, if the server requires one.
+    #[arg(long, env = "STARSEARCH_TOKEN", global = true, hide_env_values = true)]
+    token: Option<String>,
+
+    /// Search the stars of all users and show who starred each result.
+    #[arg(long)]
+    team: bool,
+
     /// Trigger a quick re-index on the server.
     #[arg(short, long)]
     refresh: bool,
 
+    /// Perform a full instead of a quick re-index when refreshing.
+    #[arg(long, requires = "refresh")]
+    full: bool,
+
     /// Dispaly server info.
     #[arg(short, long)]
     info: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show the starred repositories most similar to the given one.
+    Similar {
+        /// The full name of the repository (e.g. "owner/name").
+        repository: String,
+    },
+
+    /// Show or set the personal note of a starred repository.
+    Note {
+        /// The full name of the repository (e.g. "owner/name").
+        repository: String,
+
+        /// The new note. Shows the current note if omitted.
+        note: Vec<String>,
+
+        /// Remove the note.
+        #[arg(long, conflicts_with = "note")]
+        clear: bool,
+    },
+
+    /// Show, add or remove custom tags of a starred repository.
+    Tag {
+        /// The full name of the repository (e.g. "owner/name").
+        repository: String,
+
+        /// The tags to add. Shows the current tags if omitted.
+        tags: Vec<String>,
+
+        /// Remove the given tags instead of adding them.
+        #[arg(short, long, requires = "tags")]
+        remove: bool,
+    },
+}
+
 pub fn run() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
@@ -80,42 +230,246 @@ pub fn run() -> Result<(), Box<dyn Error>> {
         return Ok(());
     };
 
-    let client = Client::new(endpoint);
+    let mut client = Client::new(endpoint);
+    if let Some(user) = args.user.or(cfg.as_ref().and_then(|c| c.user.clone())) {
+        client = client.with_user(user);
+    }
+    if let Some(token) = args.token.or(cfg.as_ref().and_then(|c| c.token.clone())) {
+        client = client.with_token(&token)?;
+    }
 
     if args.info {
         let server_info = client.server_info()?;
         println!(
             "Indexed repositories:  {}\n\
             Last fast index run:   {}\n\
-            Last full index run:   {}",
+            Last full index run:   {}\n\
+            Semantic search:       {}",
             style(server_info.index_count).bold(),
             date_string(server_info.index_dates.last_fast_index),
             date_string(server_info.index_dates.last_full_index),
+            match server_info.semantic_search {
+                true => style("enabled").bold().green(),
+                false => style("disabled").bold().dim(),
+            },
         );
+        if server_info.users.len() > 1 {
+            println!(
+                "Users:                 {}",
+                style(server_info.users.join(", ")).bold()
+            );
+        }
         return Ok(());
     }
 
     if args.refresh {
-        tui::print_status("Refreshing database ...");
-        client.refresh()?;
-        tui::print_success("Database successfully updated.");
+        tui::print_status("Starting refresh ...");
+        let job = client.refresh(args.full)?;
+        let job = tui::follow_job(client.job_events(job.id)?)?;
+
+        match job {
+            Some(job) if job.state == JobState::Succeeded => {
+                tui::print_success("Database successfully updated.");
+                if !job.errors.is_empty() {
+                    println!(
+                        "{} {} errors occurred while fetching READMEs",
+                        style("warning:").bold().yellow(),
+                        job.errors.len()
+                    );
+                }
+            }
+            Some(job) => {
+                let err = job.errors.last().cloned().unwrap_or_default();
+                return Err(format!("refreshing database failed: {err}").into());
+            }
+            None => return Err("refresh job has not been found".into()),
+        }
 
         return Ok(());
     }
 
-    let res = client.search(
-        &args.query.join(" "),
-        args.language.as_deref(),
-        args.limit
-            .or(cfg.as_ref().and_then(|c| c.limit))
-            .unwrap_or(5),
-    )?;
+    let limit = args
+        .limit
+        .or(cfg.as_ref().and_then(|c| c.limit))
+        .unwrap_or(5);
+
+    let mut display_mode = cfg
+        .and_then(|c| c.display_mode)
+        .unwrap_or(DisplayMode::Detailed);
+    if args.detailed {
+        display_mode = DisplayMode::Detailed;
+    }
+    if args.condensed {
+        display_mode = DisplayMode::Condensed;
+    }
+
+    match args.command {
+        Some(Command::Similar { repository }) => {
+            return print_similar(&client, &repository, limit, display_mode)
+        }
+        Some(Command::Note {
+            repository,
+            note,
+            clear,
+        }) => return set_note(&client, &repository, note, clear),
+        Some(Command::Tag {
+            repository,
+            tags,
+            remove,
+        }) => return set_tags(&client, &repository, tags, remove),
+        None => {}
+    }
+
+    let range = |after: Option<NaiveDate>, before: Option<NaiveDate>| DateRange {
+        after: after.map(start_of_day),
+        before: before.map(end_of_day),
+    };
+
+    let opts = SearchOptions {
+        filter: Filter {
+            language: args.language,
+            topics_all: args.topic,
+            topics_any: args.topic_any,
+            license: args.license,
+            owner: args.owner,
+            lists: args.list,
+            fork: args.fork,
+            archived: args.archived,
+            created: range(args.created_after, args.created_before),
+            updated: range(args.updated_after, args.updated_before),
+            starred: range(args.starred_after, args.starred_before),
+        },
+        sort: args.sort,
+        mode: args.mode,
+        facets: args.facets,
+        team: args.team,
+    };
 
-    if res.is_empty() {
+    let offset = args.page.saturating_sub(1) * limit;
+
+    let res = client.search(&args.query.join(" "), limit, offset, &opts)?;
+
+    if res.hits.is_empty() {
         println!("No results have been found. :(");
         return Ok(());
     }
 
+    println!(
+        "{} {}{}{} {} {} {}",
+        style("Showing").dim(),
+        style(res.offset + 1).dim().bold(),
+        style("-").dim(),
+        style(res.offset + res.hits.len()).dim().bold(),
+        style("of").dim(),
+        style(res.total).dim().bold(),
+        style("results:").dim()
+    );
+
+    print_results(&res.hits, display_mode);
+
+    if let Some(facets) = &res.facets {
+        print_facets(facets);
+    }
+
+    Ok(())
+}
+
+/// Looks up the starred repository with the given full name.
+fn find_repository(client: &Client, full_name: &str) -> Result<Repository, Box<dyn Error>> {
+    let Some((owner, name)) = full_name.split_once('/') else {
+        return Err(format!("invalid repository name {full_name}, expected owner/name").into());
+    };
+
+    client
+        .repository_by_name(owner, name)?
+        .ok_or_else(|| format!("repository {full_name} is not starred").into())
+}
+
+fn print_similar(
+    client: &Client,
+    full_name: &str,
+    limit: usize,
+    display_mode: DisplayMode,
+) -> Result<(), Box<dyn Error>> {
+    let repo = find_repository(client, full_name)?;
+
+    let similar = client.similar(repo.id, limit)?;
+    if similar.is_empty() {
+        println!("No similar repositories have been found. :(");
+        return Ok(());
+    }
+
+    println!(
+        "{} {}{}",
+        style("Repositories similar to").dim(),
+        style(&repo.full_name).dim().bold(),
+        style(":").dim()
+    );
+    print_results(&similar, display_mode);
+
+    Ok(())
+}
+
+fn set_note(
+    client: &Client,
+    full_name: &str,
+    note: Vec<String>,
+    clear: bool,
+) -> Result<(), Box<dyn Error>> {
+    let repo = find_repository(client, full_name)?;
+    let mut annotations = repo.annotations.unwrap_or_default();
+
+    if note.is_empty() && !clear {
+        match annotations.note {
+            Some(note) => println!("{note}"),
+            None => println!("{}", style("No note has been added yet.").dim()),
+        }
+        return Ok(());
+    }
+
+    annotations.note = (!clear).then(|| note.join(" "));
+    client
+        .set_annotations(repo.id, &annotations)?
+        .ok_or_else(|| format!("repository {full_name} is not starred"))?;
+    tui::print_success(match clear {
+        true => "Note removed.",
+        false => "Note saved.",
+    });
+
+    Ok(())
+}
+
+fn set_tags(
+    client: &Client,
+    full_name: &str,
+    tags: Vec<String>,
+    remove: bool,
+) -> Result<(), Box<dyn Error>> {
+    let repo = find_repository(client, full_name)?;
+    let mut annotations = repo.annotations.unwrap_or_default();
+
+    if !tags.is_empty() {
+        if remove {
+            annotations
+                .tags
+                .retain(|t| !tags.iter().any(|r| r.eq_ignore_ascii_case(t)));
+        } else {
+            annotations.tags.extend(tags);
+        }
+        annotations = client
+            .set_annotations(repo.id, &annotations)?
+            .ok_or_else(|| format!("repository {full_name} is not starred"))?;
+    }
+
+    match annotations.tags.is_empty() {
+        true => println!("{}", style("No tags have been added yet.").dim()),
+        false => println!("{}", annotations.tags.join(", ")),
+    }
+
+    Ok(())
+}
+
+fn print_results(repos: &[Repository], display_mode: DisplayMode) {
     let color_map = match get_color_map() {
         Ok(res) => Some(res),
         Err(err) => {
@@ -128,29 +482,34 @@ pub fn run() -> Result<(), Box<dyn Error>> {
         }
     };
 
-    println!(
-        "{} {} {}",
-        style("Found").dim(),
-        style(res.len()).dim().bold(),
-        style("results:").dim()
-    );
-
-    let mut display_mode = cfg
-        .and_then(|c| c.display_mode)
-        .unwrap_or(DisplayMode::Detailed);
-    if args.detailed {
-        display_mode = DisplayMode::Detailed;
-    }
-    if args.condensed {
-        display_mode = DisplayMode::Condensed;
-    }
-
-    res.iter().for_each(|v| match display_mode {
+    repos.iter().for_each(|v| match display_mode {
         DisplayMode::Condensed => v.print_condensed(&color_map),
         DisplayMode::Detailed => v.print_detailed(&color_map),
     });
+}
 
-    Ok(())
+fn print_facets(facets: &Facets) {
+    println!();
+
+    let groups = [
+        ("Languages:", &facets.language),
+        ("Topics:", &facets.topics),
+        ("Licenses:", &facets.license),
+        ("Owners:", &facets.owner),
+        ("Lists:", &facets.lists),
+    ];
+    for (title, values) in groups {
+        if values.is_empty() {
+            continue;
+        }
+
+        let values: Vec<_> = values
+            .iter()
+            .take(FACET_SUMMARY_VALUES)
+            .map(|FacetCount { value, count }| format!("{value} {}", style(count).dim()))
+            .collect();
+        println!("{:<11}{}", style(title).bold(), values.join(", "));
+    }
 }
 
 fn get_color_map() -> Result<LanguageMap, reqwest::Error> {
@@ -194,6 +553,13 @@ impl Printer for Repository {
             print!(" - {description}");
         }
 
+        if !self.starred_by.is_empty() {
+            print!(
+                " {}",
+                style(format!("★ {}", self.starred_by.join(", "))).dim()
+            );
+        }
+
         println!();
     }
 
@@ -209,16 +575,32 @@ impl Printer for Repository {
             style("]").dim(),
         );
 
-        if let Some(description) = &self.description {
+        let highlights = self.highlights.as_ref();
+
+        if let Some(description) = highlights.and_then(|h| h.description.as_ref()) {
+            println!("{}", highlighted(description, Style::new()));
+        } else if let Some(description) = &self.description {
             println!("{description}");
         }
 
+        if let Some(readme) = highlights.and_then(|h| h.readme.as_ref()) {
+            println!(
+                "{} {}",
+                style("README:").dim().italic(),
+                highlighted(readme, Style::new().dim())
+            );
+        }
+
         if let Some(topics) = &self.topics {
             if !topics.is_empty() {
                 println!("{}", style(cap(topics, 8).join(", ")).dim());
             }
         }
 
+        if !self.lists.is_empty() {
+            println!("{}", style(format!("☰ {}", self.lists.join(", "))).dim());
+        }
+
         if let Some(language) = &self.language {
             let clr = color_map
                 .as_ref()
@@ -232,9 +614,37 @@ impl Printer for Repository {
                 println!("⬤ {language}");
             }
         }
+
+        let starred_at = self
+            .starred_at
+            .map(|date| format!(" {}", date.format("%Y-%m-%d")));
+        let starred_by =
+            (!self.starred_by.is_empty()).then(|| format!(" by {}", self.starred_by.join(", ")));
+        if starred_at.is_some() || starred_by.is_some() {
+            println!(
+                "{}",
+                style(format!(
+                    "★ starred{}{}",
+                    starred_at.unwrap_or_default(),
+                    starred_by.unwrap_or_default()
+                ))
+                .dim()
+            );
+        }
     }
 }
 
+/// Renders the text in the given style with its matches emphasized.
+fn highlighted(text: &HighlightedText, base: Style) -> String {
+    text.parts()
+        .into_iter()
+        .map(|(part, matched)| match matched {
+            true => style(part).yellow().bold().to_string(),
+            false => base.apply_to(part).to_string(),
+        })
+        .collect()
+}
+
 fn cap(v: &[String], max: usize) -> Vec<String> {
     if v.len() < max {
         return v.to_vec();
@@ -247,6 +657,10 @@ fn cap(v: &[String], max: usize) -> Vec<String> {
         .collect()
 }
 
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    date.and_time(Default::default()).and_utc()
+}
+
 fn date_string(date: Option<DateTime<Local>>) -> impl fmt::Display {
     const DATE_FORMAT: &str = "%Y-%m-%d %H:%M (%Z)";
     let now = Local::now();
@@ -268,3 +682,62 @@ fn main() {
         exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn args_are_consistent() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn parses_free_text_as_query() {
+        let args = Args::try_parse_from(["starsearch-cli", "rust", "note", "parser"]).unwrap();
+        assert!(args.command.is_none());
+        assert_eq!(args.query, ["rust", "note", "parser"]);
+    }
+
+    #[test]
+    fn parses_query_starting_with_subcommand_after_separator() {
+        let args =
+            Args::try_parse_from(["starsearch-cli", "--", "note", "taking", "apps"]).unwrap();
+        assert!(args.command.is_none());
+        assert_eq!(args.query, ["note", "taking", "apps"]);
+
+        let args =
+            Args::try_parse_from(["starsearch-cli", "--team", "--", "similar", "tools"]).unwrap();
+        assert!(args.team);
+        assert_eq!(args.query, ["similar", "tools"]);
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        let args =
+            Args::try_parse_from(["starsearch-cli", "note", "owner/repo", "nice", "app"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Note { ref repository, ref note, clear: false })
+                if repository == "owner/repo" && note == &["nice", "app"]
+        ));
+        assert!(args.query.is_empty());
+
+        let args =
+            Args::try_parse_from(["starsearch-cli", "similar", "owner/repo", "-n", "3"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Similar { ref repository }) if repository == "owner/repo"
+        ));
+        assert_eq!(args.limit, Some(3));
+    }
+
+    #[test]
+    fn parses_subcommand_names_after_search_args_as_query() {
+        let args = Args::try_parse_from(["starsearch-cli", "--team", "similar", "tools"]).unwrap();
+        assert!(args.command.is_none());
+        assert!(args.team);
+        assert_eq!(args.query, ["similar", "tools"]);
+    }
+}
@@ -1,5 +1,10 @@
 use console::{style, Term};
+use indicatif::{ProgressBar, ProgressStyle};
+use starsearch_sdk::client::JobEvents;
+use starsearch_sdk::errors::Result;
+use starsearch_sdk::models::{Job, JobState};
 use std::io::{self, Write};
+use std::time::Duration;
 
 #[allow(unused_must_use)]
 pub fn print_status(v: &str) {
@@ -13,3 +18,51 @@ pub fn print_success(v: &str) {
     Term::stdout().clear_line();
     println!("{}", style(v).green());
 }
+
+/// Displays the progress of a job until it has finished and returns the
+/// last received state of the job.
+pub fn follow_job(events: JobEvents) -> Result<Option<Job>> {
+    let bar = ProgressBar::new_spinner();
+    bar.enable_steady_tick(Duration::from_millis(100));
+
+    let mut last = None;
+    for job in events {
+        let job = job?;
+
+        if job.state == JobState::Queued {
+            bar.set_message(
+                style("Waiting for the running scrape to finish ...")
+                    .dim()
+                    .italic()
+                    .to_string(),
+            );
+        } else if let Some(total) = job.progress.repos_total {
+            if bar.length() != Some(total as u64) {
+                bar.set_style(
+                    ProgressStyle::with_template("{msg} [{bar:30}] {pos}/{len}")
+                        .unwrap()
+                        .progress_chars("=> "),
+                );
+                bar.set_length(total as u64);
+                bar.set_message(style("Fetching READMEs").dim().italic().to_string());
+            }
+            bar.set_position(job.progress.readmes_fetched as u64);
+        } else {
+            bar.set_message(
+                style(format!(
+                    "Fetching starred repositories (page {}) ...",
+                    job.progress.pages_fetched + 1
+                ))
+                .dim()
+                .italic()
+                .to_string(),
+            );
+        }
+
+        last = Some(job);
+    }
+
+    bar.finish_and_clear();
+
+    Ok(last)
+}
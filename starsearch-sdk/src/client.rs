@@ -1,9 +1,22 @@
-use crate::errors::Result;
-use crate::models::{Repository, ServerInfo};
+use crate::errors::{Error, Result};
+use crate::models::{
+    Annotations, Job, Repository, SearchOptions, SearchResponse, ServerInfo, MAX_TOTAL_HITS,
+};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{IntoUrl, StatusCode};
+use serde::de::DeserializeOwned;
+use std::io::{BufRead, BufReader, Lines};
+use std::time::Duration;
+
+/// Timeout of event streams, which stay open for the whole duration of a job.
+const EVENTS_TIMEOUT: Duration = Duration::from_secs(24 * 3600);
 
 pub struct Client {
     endpoint: String,
     client: reqwest::blocking::Client,
+    /// The user of which the stars are accessed. The server's default user
+    /// is used if none is set.
+    user: Option<String>,
 }
 
 impl Client {
@@ -13,26 +26,123 @@ impl Client {
         Self {
             endpoint: endpoint.into(),
             client,
+            user: None,
         }
     }
 
+    /// Returns the client accessing the stars of the given user instead of
+    /// the ones of the server's default user.
+    pub fn with_user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// Returns the client sending the given API token as bearer token with
+    /// every request, which is required if the server has tokens configured.
+    pub fn with_token(mut self, token: &str) -> Result<Self> {
+        let mut value =
+            HeaderValue::from_str(&format!("Bearer {token}")).map_err(|_| Error::InvalidToken)?;
+        value.set_sensitive(true);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, value);
+        self.client = reqwest::blocking::Client::builder()
+            .default_headers(headers)
+            .build()?;
+        Ok(self)
+    }
+
     pub fn search(
         &self,
         query: &str,
-        language: Option<&str>,
         limit: usize,
-    ) -> Result<Vec<Repository>> {
+        offset: usize,
+        opts: &SearchOptions,
+    ) -> Result<SearchResponse> {
         let limit = limit.to_string();
-        let mut query_params = vec![("query", query), ("limit", &limit)];
-
-        if let Some(language) = language {
-            query_params.push(("language", language));
-        }
+        let offset = offset.to_string();
+        let query_params = [("query", query), ("limit", &limit), ("offset", &offset)];
 
         let res = self
             .client
             .get(format!("{}/api/search", self.endpoint))
+            .query(&self.user_query())
             .query(&query_params)
+            .query(&opts.query_pairs())
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        Ok(res)
+    }
+
+    /// Returns an iterator over all pages of the results of the given search
+    /// query, each containing up to `limit` repositories.
+    pub fn search_pages<'a>(
+        &'a self,
+        query: &'a str,
+        limit: usize,
+        opts: &'a SearchOptions,
+    ) -> SearchPages<'a> {
+        SearchPages {
+            client: self,
+            query,
+            limit,
+            opts,
+            offset: Some(0),
+        }
+    }
+
+    /// Returns the indexed repository with the given ID, if present.
+    pub fn repository(&self, id: u32) -> Result<Option<Repository>> {
+        self.get_optional(format!("{}/api/repositories/{id}", self.endpoint))
+    }
+
+    /// Returns the indexed repository with the given owner and name, if
+    /// present. Both are matched case-insensitively.
+    pub fn repository_by_name(&self, owner: &str, name: &str) -> Result<Option<Repository>> {
+        let invalid_endpoint = || Error::InvalidEndpoint(self.endpoint.clone());
+        let mut url = reqwest::Url::parse(&self.endpoint).map_err(|_| invalid_endpoint())?;
+        url.path_segments_mut()
+            .map_err(|_| invalid_endpoint())?
+            .pop_if_empty()
+            .extend(["api", "repositories", "by-name", owner, name]);
+
+        self.get_optional(url)
+    }
+
+    /// Replaces the notes and tags of the repository with the given ID and
+    /// returns them as stored. Returns `None` if the repository is not
+    /// indexed.
+    pub fn set_annotations(
+        &self,
+        id: u32,
+        annotations: &Annotations,
+    ) -> Result<Option<Annotations>> {
+        let res = self
+            .client
+            .put(format!(
+                "{}/api/repositories/{id}/annotations",
+                self.endpoint
+            ))
+            .query(&self.user_query())
+            .json(annotations)
+            .send()?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        Ok(Some(res.error_for_status()?.json()?))
+    }
+
+    /// Returns up to `limit` starred repositories which are the most similar
+    /// to the repository with the given ID.
+    pub fn similar(&self, id: u32, limit: usize) -> Result<Vec<Repository>> {
+        let res = self
+            .client
+            .get(format!("{}/api/repositories/{id}/similar", self.endpoint))
+            .query(&self.user_query())
+            .query(&[("limit", limit)])
             .send()?
             .error_for_status()?
             .json()?;
@@ -40,22 +150,203 @@ impl Client {
         Ok(res)
     }
 
-    pub fn refresh(&self) -> Result<()> {
-        self.client
+    /// Starts a scrape job on the server and returns it without waiting for
+    /// it to finish.
+    pub fn refresh(&self, full: bool) -> Result<Job> {
+        let res = self
+            .client
             .post(format!("{}/api/refresh", self.endpoint))
+            .query(&self.user_query())
+            .query(&[("full", full)])
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        Ok(res)
+    }
+
+    pub fn jobs(&self) -> Result<Vec<Job>> {
+        let res = self
+            .client
+            .get(format!("{}/api/jobs", self.endpoint))
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        Ok(res)
+    }
+
+    pub fn job(&self, id: u64) -> Result<Job> {
+        let res = self
+            .client
+            .get(format!("{}/api/jobs/{id}", self.endpoint))
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        Ok(res)
+    }
+
+    /// Returns an iterator over the updates of the given job. The iterator
+    /// ends after the job has finished.
+    pub fn job_events(&self, id: u64) -> Result<JobEvents> {
+        let res = self
+            .client
+            .get(format!("{}/api/jobs/{id}/events", self.endpoint))
+            .timeout(EVENTS_TIMEOUT)
             .send()?
             .error_for_status()?;
 
-        Ok(())
+        Ok(JobEvents {
+            lines: BufReader::new(res).lines(),
+        })
+    }
+
+    /// Returns the query parameter selecting the user, if one is set.
+    fn user_query(&self) -> Vec<(&str, &str)> {
+        self.user
+            .iter()
+            .map(|user| ("user", user.as_str()))
+            .collect()
+    }
+
+    /// Sends a GET request and returns the deserialized response or `None` if
+    /// the server responded with 404.
+    fn get_optional<T: DeserializeOwned>(&self, url: impl IntoUrl) -> Result<Option<T>> {
+        let res = self.client.get(url).query(&self.user_query()).send()?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        Ok(Some(res.error_for_status()?.json()?))
     }
 
     pub fn server_info(&self) -> Result<ServerInfo> {
         let res = self
             .client
             .get(format!("{}/api/serverinfo", self.endpoint))
+            .query(&self.user_query())
             .send()?
             .error_for_status()?
             .json()?;
         Ok(res)
     }
 }
+
+/// Iterator over the pages of search results.
+pub struct SearchPages<'a> {
+    client: &'a Client,
+    query: &'a str,
+    limit: usize,
+    opts: &'a SearchOptions,
+    /// The offset of the next page, or `None` after the last page.
+    offset: Option<usize>,
+}
+
+impl Iterator for SearchPages<'_> {
+    type Item = Result<SearchResponse>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset.take()?;
+
+        let res = match self
+            .client
+            .search(self.query, self.limit, offset, self.opts)
+        {
+            Ok(res) => res,
+            Err(err) => return Some(Err(err)),
+        };
+
+        // The server serves the last reachable page in place of pages past
+        // the maximum offset, which must not be repeated.
+        if res.offset != offset {
+            return None;
+        }
+        self.offset = next_offset(&res);
+
+        Some(Ok(res))
+    }
+}
+
+/// Returns the offset of the page following the given one, or `None` if it
+/// is the last reachable page.
+fn next_offset(res: &SearchResponse) -> Option<usize> {
+    let next = res.offset + res.hits.len();
+    (!res.hits.is_empty() && next < res.total.min(MAX_TOTAL_HITS)).then_some(next)
+}
+
+/// Iterator over the job updates sent by the server as Server-Sent Events.
+pub struct JobEvents {
+    lines: Lines<BufReader<reqwest::blocking::Response>>,
+}
+
+impl Iterator for JobEvents {
+    type Item = Result<Job>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut data = String::new();
+
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.into())),
+            };
+
+            if let Some(value) = line.strip_prefix("data:") {
+                data.push_str(value.trim_start());
+            } else if line.is_empty() && !data.is_empty() {
+                return Some(serde_json::from_str(&data).map_err(Into::into));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(offset: usize, hits: usize, total: usize) -> SearchResponse {
+        let repo: Repository = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "repo",
+            "full_name": "owner/repo",
+            "owner": { "id": 1, "login": "owner" },
+            "description": null,
+            "fork": false,
+            "url": "https://api.github.com/repos/owner/repo",
+            "html_url": "https://github.com/owner/repo",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "language": null,
+            "license": null,
+            "topics": null,
+            "readme_content": null,
+            "disabled": false,
+        }))
+        .unwrap();
+        SearchResponse {
+            hits: vec![repo; hits],
+            total,
+            offset,
+            limit: 10,
+            facets: None,
+        }
+    }
+
+    #[test]
+    fn next_offset_follows_page() {
+        assert_eq!(next_offset(&response(0, 10, 25)), Some(10));
+        assert_eq!(next_offset(&response(20, 5, 25)), None);
+        assert_eq!(next_offset(&response(30, 0, 25)), None);
+    }
+
+    #[test]
+    fn next_offset_stops_at_max_total_hits() {
+        let total = MAX_TOTAL_HITS * 2;
+        assert_eq!(
+            next_offset(&response(MAX_TOTAL_HITS - 20, 10, total)),
+            Some(MAX_TOTAL_HITS - 10)
+        );
+        assert_eq!(next_offset(&response(MAX_TOTAL_HITS - 10, 10, total)), None);
+    }
+}
@@ -6,4 +6,28 @@ pub type Result<T, E = Error> = core::result::Result<T, E>;
 pub enum Error {
     #[error("request failed: {0}")]
     RequestError(#[from] reqwest::Error),
+
+    #[error("reading response failed: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("invalid response: {0}")]
+    InvalidResponse(#[from] serde_json::Error),
+
+    #[error("invalid endpoint: {0}")]
+    InvalidEndpoint(String),
+
+    #[error("invalid API token")]
+    InvalidToken,
+
+    #[error("invalid sort value: {0}")]
+    InvalidSort(String),
+
+    #[error("invalid search mode: {0}")]
+    InvalidSearchMode(String),
+
+    #[error("invalid filter: {0}")]
+    InvalidFilter(String),
+
+    #[error("invalid parameter: {0}")]
+    InvalidParameter(String),
 }
@@ -1,5 +1,8 @@
+use crate::errors::Error;
 use chrono::{DateTime, Local, Utc};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Repository {
@@ -13,11 +16,135 @@ pub struct Repository {
     pub html_url: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub starred_at: Option<DateTime<Utc>>,
     pub language: Option<String>,
     pub license: Option<License>,
     pub topics: Option<Vec<String>>,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub default_branch: Option<String>,
     pub readme_content: Option<String>,
+    #[serde(default)]
+    pub readme_path: Option<String>,
     pub disabled: bool,
+    /// Time the repository has last been scraped from GitHub.
+    #[serde(default)]
+    pub scraped_at: Option<DateTime<Utc>>,
+    /// Embedding vector computed by the server for semantic search. It is
+    /// never serialized.
+    #[serde(skip)]
+    pub embedding: Option<Vec<f32>>,
+    /// Names of the user's star lists containing the repository.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lists: Vec<String>,
+    /// Notes and tags added by the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Annotations>,
+    /// Users who starred the repository. Only set on hits of team-wide
+    /// searches.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub starred_by: Vec<String>,
+    /// Passages matching the search query. Only set on search hits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highlights: Option<Highlights>,
+}
+
+/// Free-text notes and custom tags added to a repository by the user. They
+/// are stored separately from the scraped data, so scrapes never overwrite
+/// them.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Annotations {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.note.is_none() && self.tags.is_empty()
+    }
+
+    /// Returns the annotations with surrounding whitespace, empty values and
+    /// case-insensitively duplicate tags removed.
+    pub fn normalized(self) -> Self {
+        let note = self
+            .note
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty());
+
+        let mut tags: Vec<String> = vec![];
+        for tag in self.tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.into());
+            }
+        }
+
+        Self { note, tags }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct Highlights {
+    /// The description with the matched terms. Only set if the description
+    /// matched the query.
+    pub description: Option<HighlightedText>,
+    /// A short passage of the README around the matched terms. Only set if
+    /// the README matched the query.
+    pub readme: Option<HighlightedText>,
+}
+
+impl Highlights {
+    pub fn is_empty(&self) -> bool {
+        self.description.is_none() && self.readme.is_none()
+    }
+}
+
+/// A text together with the positions of the search terms matched in it.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct HighlightedText {
+    pub text: String,
+    /// Ordered, non-overlapping matches in `text`.
+    pub matches: Vec<MatchRange>,
+}
+
+/// Position of a match as byte offset and length.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct MatchRange {
+    pub start: usize,
+    pub length: usize,
+}
+
+impl HighlightedText {
+    /// Splits the text into consecutive parts, each flagged whether it is a
+    /// match or not.
+    pub fn parts(&self) -> Vec<(&str, bool)> {
+        let mut parts = vec![];
+        let mut pos = 0;
+
+        for m in &self.matches {
+            let end = m.start + m.length;
+            let (Some(before), Some(matched)) =
+                (self.text.get(pos..m.start), self.text.get(m.start..end))
+            else {
+                continue;
+            };
+            if !before.is_empty() {
+                parts.push((before, false));
+            }
+            parts.push((matched, true));
+            pos = end;
+        }
+
+        if let Some(rest) = self.text.get(pos..).filter(|rest| !rest.is_empty()) {
+            parts.push((rest, false));
+        }
+
+        parts
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -39,6 +166,262 @@ pub struct User {
     pub login: String,
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sort {
+    #[serde(rename = "starred_at:desc")]
+    StarredAtDesc,
+    #[serde(rename = "starred_at:asc")]
+    StarredAtAsc,
+}
+
+impl Sort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::StarredAtDesc => "starred_at:desc",
+            Self::StarredAtAsc => "starred_at:asc",
+        }
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Sort {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "starred_at:desc" | "starred_at" => Ok(Self::StarredAtDesc),
+            "starred_at:asc" => Ok(Self::StarredAtAsc),
+            _ => Err(Error::InvalidSort(s.into())),
+        }
+    }
+}
+
+/// How search queries are matched against repositories.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Full-text search on the query terms.
+    #[default]
+    Keyword,
+    /// Ranks repositories by the similarity of their embedding to the one of
+    /// the query.
+    Semantic,
+    /// Blends keyword ranking and semantic similarity.
+    Hybrid,
+}
+
+impl SearchMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Keyword => "keyword",
+            Self::Semantic => "semantic",
+            Self::Hybrid => "hybrid",
+        }
+    }
+}
+
+impl fmt::Display for SearchMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SearchMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keyword" => Ok(Self::Keyword),
+            "semantic" => Ok(Self::Semantic),
+            "hybrid" => Ok(Self::Hybrid),
+            _ => Err(Error::InvalidSearchMode(s.into())),
+        }
+    }
+}
+
+/// Optional filter and sort parameters of a search or listing.
+#[derive(Clone, Debug, Default)]
+pub struct SearchOptions {
+    pub filter: Filter,
+    pub sort: Option<Sort>,
+    /// Only applies to searches.
+    pub mode: SearchMode,
+    /// Whether to compute the facet distribution of the matching
+    /// repositories.
+    pub facets: bool,
+    /// Whether to search the stars of all users instead of only the ones of
+    /// the selected user.
+    pub team: bool,
+}
+
+impl SearchOptions {
+    /// Returns the options as URL query parameters.
+    pub fn query_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = self.filter.query_pairs();
+        if let Some(sort) = self.sort {
+            pairs.push(("sort", sort.to_string()));
+        }
+        if self.mode != SearchMode::Keyword {
+            pairs.push(("mode", self.mode.to_string()));
+        }
+        if self.facets {
+            pairs.push(("facets", true.to_string()));
+        }
+        if self.team {
+            pairs.push(("team", true.to_string()));
+        }
+        pairs
+    }
+}
+
+/// Restricts searches and listings to repositories matching all of the set
+/// conditions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    pub language: Option<String>,
+    /// Repositories must have all of these topics.
+    pub topics_all: Vec<String>,
+    /// Repositories must have at least one of these topics.
+    pub topics_any: Vec<String>,
+    /// SPDX identifier of the license, e.g. `MIT`.
+    pub license: Option<String>,
+    /// Login of the repository owner.
+    pub owner: Option<String>,
+    /// Repositories must be in all of these star lists.
+    pub lists: Vec<String>,
+    pub fork: Option<bool>,
+    pub archived: Option<bool>,
+    pub created: DateRange,
+    pub updated: DateRange,
+    pub starred: DateRange,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Adds all conditions of `other` to this filter. Single-valued
+    /// conditions set in `other` replace the ones of this filter.
+    pub fn merge(&mut self, other: Filter) {
+        self.topics_all.extend(other.topics_all);
+        self.topics_any.extend(other.topics_any);
+        self.lists.extend(other.lists);
+        self.language = other.language.or(self.language.take());
+        self.license = other.license.or(self.license.take());
+        self.owner = other.owner.or(self.owner.take());
+        self.fork = other.fork.or(self.fork);
+        self.archived = other.archived.or(self.archived);
+        self.created.merge(other.created);
+        self.updated.merge(other.updated);
+        self.starred.merge(other.starred);
+    }
+
+    /// Returns the filter as URL query parameters as accepted by the
+    /// search endpoint.
+    pub fn query_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = vec![];
+
+        if let Some(language) = &self.language {
+            pairs.push(("language", language.clone()));
+        }
+        pairs.extend(self.topics_all.iter().map(|t| ("topic", t.clone())));
+        pairs.extend(self.topics_any.iter().map(|t| ("topic_any", t.clone())));
+        if let Some(license) = &self.license {
+            pairs.push(("license", license.clone()));
+        }
+        if let Some(owner) = &self.owner {
+            pairs.push(("owner", owner.clone()));
+        }
+        pairs.extend(self.lists.iter().map(|l| ("list", l.clone())));
+        if let Some(fork) = self.fork {
+            pairs.push(("fork", fork.to_string()));
+        }
+        if let Some(archived) = self.archived {
+            pairs.push(("archived", archived.to_string()));
+        }
+
+        let ranges = [
+            ("created_after", "created_before", &self.created),
+            ("updated_after", "updated_before", &self.updated),
+            ("starred_after", "starred_before", &self.starred),
+        ];
+        for (after_key, before_key, range) in ranges {
+            if let Some(after) = range.after {
+                pairs.push((after_key, after.to_rfc3339()));
+            }
+            if let Some(before) = range.before {
+                pairs.push((before_key, before.to_rfc3339()));
+            }
+        }
+
+        pairs
+    }
+}
+
+/// Inclusive range of dates. Unset bounds are unbounded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+}
+
+impl DateRange {
+    pub fn is_empty(&self) -> bool {
+        self.after.is_none() && self.before.is_none()
+    }
+
+    fn merge(&mut self, other: DateRange) {
+        self.after = other.after.or(self.after);
+        self.before = other.before.or(self.before);
+    }
+}
+
+/// Maximum number of hits reachable by paging through search results. Pages
+/// at larger offsets are not served.
+pub const MAX_TOTAL_HITS: usize = 10_000;
+
+/// A page of search or listing results.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SearchResponse {
+    pub hits: Vec<Repository>,
+    /// The total number of matching repositories. For searches, this may be
+    /// an estimate.
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    /// Only present if requested via [`SearchOptions::facets`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub facets: Option<Facets>,
+}
+
+/// Number of matching repositories per value of the facet attributes. Each
+/// list is ordered by descending count and only holds the most frequent
+/// values.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct Facets {
+    pub language: Vec<FacetCount>,
+    pub topics: Vec<FacetCount>,
+    /// SPDX identifiers of the licenses.
+    pub license: Vec<FacetCount>,
+    /// Logins of the repository owners.
+    pub owner: Vec<FacetCount>,
+    /// Names of the star lists.
+    #[serde(default)]
+    pub lists: Vec<FacetCount>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: usize,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug, Default)]
 pub struct IndexDates {
     pub last_fast_index: Option<DateTime<Local>>,
@@ -50,4 +433,95 @@ pub struct ServerInfo {
     #[serde(flatten)]
     pub index_dates: IndexDates,
     pub index_count: usize,
+    /// Whether the semantic and hybrid search modes are available.
+    #[serde(default)]
+    pub semantic_search: bool,
+    /// Users of which the stars are searchable, the default one first.
+    #[serde(default)]
+    pub users: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    /// Only newly starred repositories are indexed.
+    Fast,
+    /// All starred repositories are re-indexed and unstarred ones removed.
+    Full,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// The job waits for the currently running job to finish.
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct JobProgress {
+    pub pages_fetched: usize,
+    /// The number of repositories to be indexed. This is known as soon as all
+    /// pages of starred repositories have been fetched.
+    pub repos_total: Option<usize>,
+    pub readmes_fetched: usize,
+    pub repos_indexed: usize,
+}
+
+/// A scrape run, either triggered manually or by the scheduler.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Job {
+    pub id: u64,
+    pub kind: JobKind,
+    /// The user of which the stars are scraped.
+    #[serde(default)]
+    pub user: String,
+    pub state: JobState,
+    pub progress: JobProgress,
+    pub errors: Vec<String>,
+    pub created_at: DateTime<Local>,
+    pub started_at: Option<DateTime<Local>>,
+    pub finished_at: Option<DateTime<Local>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_annotations_trim_and_deduplicate_tags() {
+        let annotations = Annotations {
+            note: Some("  Great parser\n".into()),
+            tags: vec![
+                " rust ".into(),
+                "CLI".into(),
+                "Rust".into(),
+                "   ".into(),
+                "cli".into(),
+            ],
+        }
+        .normalized();
+        assert_eq!(annotations.note.as_deref(), Some("Great parser"));
+        assert_eq!(annotations.tags, ["rust", "CLI"]);
+    }
+
+    #[test]
+    fn normalized_annotations_drop_empty_note() {
+        let annotations = Annotations {
+            note: Some(" \n ".into()),
+            tags: vec!["".into()],
+        }
+        .normalized();
+        assert_eq!(annotations.note, None);
+        assert!(annotations.tags.is_empty());
+        assert!(annotations.is_empty());
+    }
 }
@@ -0,0 +1,292 @@
+use crate::errors::{Error, Result};
+use crate::models::{DateRange, Filter};
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+
+/// Splits the given search query into the free text and the filter set via
+/// inline `key:value` expressions, e.g. `lang:rust topic:cli parser`.
+///
+/// Supported keys are `lang` (or `language`), `topic`, `license`, `owner`,
+/// `list`, `fork`, `archived`, `created`, `updated` and `starred`. Every
+/// `topic:` expression must match, while comma separated topics like
+/// `topic:cli,tui` match any of them. Likewise, repositories must be in all
+/// star lists given via `list:`. Dates can be given as `>2024-01-01`,
+/// `>=2024-01-01`, `<2024-01-01`, `<=2024-01-01`, `2024-01-01..2024-06-30`
+/// including both days or as a single day. Values containing spaces can be
+/// quoted like `owner:"some owner"`.
+///
+/// Words with unknown keys are kept as part of the free text.
+pub fn parse(query: &str) -> Result<(String, Filter)> {
+    let mut filter = Filter::default();
+    let mut text = vec![];
+
+    for token in tokenize(query) {
+        let Some((key, value)) = token.split_once(':') else {
+            text.push(token);
+            continue;
+        };
+
+        let value = unquote(value);
+        let key = key.to_lowercase();
+        match key.as_str() {
+            "lang" | "language" => filter.language = Some(value.into()),
+            "topic" | "topics" => {
+                let topics: Vec<_> = value.split(',').filter(|t| !t.is_empty()).collect();
+                if topics.len() > 1 {
+                    filter.topics_any.extend(topics.into_iter().map(Into::into));
+                } else {
+                    filter.topics_all.extend(topics.into_iter().map(Into::into));
+                }
+            }
+            "license" => filter.license = Some(value.into()),
+            "owner" | "user" => filter.owner = Some(value.into()),
+            "list" => filter.lists.push(value.into()),
+            "fork" => filter.fork = Some(parse_bool(&key, value)?),
+            "archived" => filter.archived = Some(parse_bool(&key, value)?),
+            "created" => filter.created = parse_range(&key, value)?,
+            "updated" => filter.updated = parse_range(&key, value)?,
+            "starred" => filter.starred = parse_range(&key, value)?,
+            _ => text.push(token),
+        }
+    }
+
+    Ok((text.join(" "), filter))
+}
+
+/// Parses either an RFC 3339 timestamp or a plain `YYYY-MM-DD` date, which
+/// is interpreted as midnight UTC.
+pub fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.to_utc());
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    Some(date.and_time(Default::default()).and_utc())
+}
+
+/// Parses the upper bound of a date range like [`parse_date`], except that
+/// plain dates are interpreted as the end of the day, so that the whole day
+/// is included.
+pub fn parse_end_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.to_utc());
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    Some(end_of_day(date))
+}
+
+/// Returns the last second of the given day in UTC, which is the inclusive
+/// upper bound of ranges ending on that day.
+pub fn end_of_day(date: NaiveDate) -> DateTime<Utc> {
+    date.and_time(Default::default()).and_utc() + TimeDelta::days(1) - TimeDelta::seconds(1)
+}
+
+/// Splits the query at whitespace outside of double quotes.
+fn tokenize(query: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut start = None;
+    let mut quoted = false;
+
+    for (idx, c) in query.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if let Some(start) = start.take() {
+                    tokens.push(&query[start..idx]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(idx);
+    }
+
+    if let Some(start) = start {
+        tokens.push(&query[start..]);
+    }
+
+    tokens
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .map(|v| v.strip_suffix('"').unwrap_or(v))
+        .unwrap_or(value)
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" => Ok(true),
+        "false" | "no" => Ok(false),
+        _ => Err(Error::InvalidFilter(format!("{key}:{value}"))),
+    }
+}
+
+fn parse_range(key: &str, value: &str) -> Result<DateRange> {
+    let invalid = || Error::InvalidFilter(format!("{key}:{value}"));
+    // Lower bounds start at the beginning and upper bounds end at the end of
+    // plain dates, so that the given days are included.
+    let start = |v: &str| parse_date(v).ok_or_else(invalid);
+    let end = |v: &str| parse_end_date(v).ok_or_else(invalid);
+    let second = TimeDelta::seconds(1);
+
+    let range = if let Some(v) = value.strip_prefix(">=") {
+        DateRange {
+            after: Some(start(v)?),
+            before: None,
+        }
+    } else if let Some(v) = value.strip_prefix('>') {
+        DateRange {
+            after: Some(end(v)? + second),
+            before: None,
+        }
+    } else if let Some(v) = value.strip_prefix("<=") {
+        DateRange {
+            after: None,
+            before: Some(end(v)?),
+        }
+    } else if let Some(v) = value.strip_prefix('<') {
+        DateRange {
+            after: None,
+            before: Some(start(v)? - second),
+        }
+    } else if let Some((after, before)) = value.split_once("..") {
+        DateRange {
+            after: (!after.is_empty()).then(|| start(after)).transpose()?,
+            before: (!before.is_empty()).then(|| end(before)).transpose()?,
+        }
+    } else {
+        // A single date matches the whole day.
+        DateRange {
+            after: Some(start(value)?),
+            before: Some(end(value)?),
+        }
+    };
+
+    Ok(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> Option<DateTime<Utc>> {
+        Some(DateTime::parse_from_rfc3339(value).unwrap().to_utc())
+    }
+
+    fn range(value: &str) -> DateRange {
+        parse_range("created", value).unwrap()
+    }
+
+    #[test]
+    fn parse_splits_text_and_filters() {
+        let (text, filter) = parse("fast lang:rust parser topic:cli owner:rust-lang").unwrap();
+        assert_eq!(text, "fast parser");
+        assert_eq!(
+            filter,
+            Filter {
+                language: Some("rust".into()),
+                topics_all: vec!["cli".into()],
+                owner: Some("rust-lang".into()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn parse_keeps_unknown_keys_as_text() {
+        let (text, filter) = parse("foo:bar https://example.com").unwrap();
+        assert_eq!(text, "foo:bar https://example.com");
+        assert!(filter.is_empty());
+    }
+
+    #[test]
+    fn parse_handles_quoted_values() {
+        let (text, filter) = parse(r#"owner:"some owner" lang:"c sharp" tool"#).unwrap();
+        assert_eq!(text, "tool");
+        assert_eq!(filter.owner.as_deref(), Some("some owner"));
+        assert_eq!(filter.language.as_deref(), Some("c sharp"));
+    }
+
+    #[test]
+    fn parse_combines_topics_and_lists() {
+        let (_, filter) = parse("topic:cli topic:tui,gui list:tools list:later").unwrap();
+        assert_eq!(filter.topics_all, ["cli"]);
+        assert_eq!(filter.topics_any, ["tui", "gui"]);
+        assert_eq!(filter.lists, ["tools", "later"]);
+    }
+
+    #[test]
+    fn parse_reads_booleans() {
+        let (_, filter) = parse("fork:yes archived:false").unwrap();
+        assert_eq!(filter.fork, Some(true));
+        assert_eq!(filter.archived, Some(false));
+
+        assert!(matches!(
+            parse("fork:maybe"),
+            Err(Error::InvalidFilter(value)) if value == "fork:maybe"
+        ));
+    }
+
+    #[test]
+    fn parse_reads_date_ranges() {
+        let (_, filter) = parse("starred:2024-06-30 updated:>=2024-01-01").unwrap();
+        assert_eq!(filter.starred, range("2024-06-30"));
+        assert_eq!(filter.updated, range(">=2024-01-01"));
+        assert!(filter.created.is_empty());
+
+        assert!(parse("created:>yesterday").is_err());
+        assert!(parse("created:2024-01-01..soon").is_err());
+    }
+
+    #[test]
+    fn single_day_includes_whole_day() {
+        let range = range("2024-06-30");
+        assert_eq!(range.after, date("2024-06-30T00:00:00Z"));
+        assert_eq!(range.before, date("2024-06-30T23:59:59Z"));
+    }
+
+    #[test]
+    fn greater_than_excludes_day() {
+        assert_eq!(range(">2024-01-01").after, date("2024-01-02T00:00:00Z"));
+        assert_eq!(range(">=2024-01-01").after, date("2024-01-01T00:00:00Z"));
+        assert_eq!(range(">2024-01-01").before, None);
+    }
+
+    #[test]
+    fn less_than_excludes_day() {
+        assert_eq!(range("<2024-06-30").before, date("2024-06-29T23:59:59Z"));
+        assert_eq!(range("<=2024-06-30").before, date("2024-06-30T23:59:59Z"));
+        assert_eq!(range("<2024-06-30").after, None);
+    }
+
+    #[test]
+    fn ranges_include_both_days() {
+        let range = range("2024-01-01..2024-06-30");
+        assert_eq!(range.after, date("2024-01-01T00:00:00Z"));
+        assert_eq!(range.before, date("2024-06-30T23:59:59Z"));
+
+        let open_start = parse_range("created", "..2024-06-30").unwrap();
+        assert_eq!(open_start.after, None);
+        assert_eq!(open_start.before, date("2024-06-30T23:59:59Z"));
+
+        let open_end = parse_range("created", "2024-01-01..").unwrap();
+        assert_eq!(open_end.after, date("2024-01-01T00:00:00Z"));
+        assert_eq!(open_end.before, None);
+    }
+
+    #[test]
+    fn timestamps_are_taken_as_is() {
+        let range = range("2024-01-01T12:00:00Z..2024-01-02T12:00:00Z");
+        assert_eq!(range.after, date("2024-01-01T12:00:00Z"));
+        assert_eq!(range.before, date("2024-01-02T12:00:00Z"));
+
+        assert_eq!(
+            parse_range("created", ">2024-01-01T12:00:00Z")
+                .unwrap()
+                .after,
+            date("2024-01-01T12:00:01Z")
+        );
+    }
+}
@@ -2,15 +2,52 @@ use rocket::figment::providers::Env;
 use rocket::figment::Figment;
 use serde::Deserialize;
 
-#[derive(Deserialize)]
+const DEFAULT_DATA_DIR: &str = "data";
+const DEFAULT_GITHUB_URL: &str = "https://github.com";
+const DEFAULT_GITHUB_API_URL: &str = "https://api.github.com";
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Meilisearch,
+    Tantivy,
+}
+
+#[derive(Deserialize, Clone)]
 pub struct Config {
+    /// Comma-separated GitHub usernames of which the stars are scraped.
+    #[serde(default)]
     pub github_username: String,
     pub github_apitoken: Option<String>,
-    pub meilisearch_url: String,
+    /// Credentials of the GitHub OAuth app through which users log in. Login
+    /// is only enabled if both are set.
+    pub github_client_id: Option<String>,
+    pub github_client_secret: Option<String>,
+    /// Base URL of the GitHub web app serving the OAuth endpoints.
+    pub github_url: Option<String>,
+    /// Base URL of the GitHub REST and GraphQL APIs.
+    pub github_api_url: Option<String>,
+    /// URL under which the web app is reachable, which is used to build the
+    /// OAuth callback URL and the links of feeds. The callback URL registered
+    /// with the OAuth app and the requested host are used if it is not set.
+    pub public_url: Option<String>,
+    /// Comma-separated `<scope>:<sha256 hash>` entries of the tokens required
+    /// to access the API, the web UI and the feeds. They are open if none are
+    /// set.
+    pub api_tokens: Option<String>,
+    pub search_backend: Option<Backend>,
+    pub meilisearch_url: Option<String>,
     pub meilisearch_apikey: Option<String>,
+    /// Time in which Meilisearch has to become reachable on startup. The
+    /// server keeps retrying to connect if it is not set.
+    pub meilisearch_connect_timeout_seconds: Option<u64>,
+    pub data_dir: Option<String>,
     pub skip_initial_scrape: Option<bool>,
     pub scrape_fast_interval_seconds: Option<u64>,
     pub scrape_full_interval_seconds: Option<u64>,
+    pub scrape_concurrency: Option<usize>,
+    pub embedding_model: Option<String>,
 }
 
 impl Config {
@@ -20,4 +57,30 @@ impl Config {
             .extract()
             .map_err(Box::new)
     }
+
+    pub fn data_dir(&self) -> &str {
+        self.data_dir.as_deref().unwrap_or(DEFAULT_DATA_DIR)
+    }
+
+    pub fn github_url(&self) -> &str {
+        self.github_url.as_deref().unwrap_or(DEFAULT_GITHUB_URL)
+    }
+
+    pub fn github_api_url(&self) -> &str {
+        self.github_api_url
+            .as_deref()
+            .unwrap_or(DEFAULT_GITHUB_API_URL)
+    }
+
+    /// Returns the configured GitHub usernames in their given order, without
+    /// duplicates.
+    pub fn github_usernames(&self) -> Vec<String> {
+        let mut usernames: Vec<String> = vec![];
+        for username in self.github_username.split(',').map(str::trim) {
+            if !username.is_empty() && !usernames.iter().any(|u| u.eq_ignore_ascii_case(username)) {
+                usernames.push(username.to_string());
+            }
+        }
+        usernames
+    }
 }
@@ -1,3 +1,4 @@
+use crate::embeddings::errors::EmbeddingError;
 use thiserror::Error;
 
 pub type Result<T, E = DatabaseError> = core::result::Result<T, E>;
@@ -6,4 +7,31 @@ pub type Result<T, E = DatabaseError> = core::result::Result<T, E>;
 pub enum DatabaseError {
     #[error("meilisearch error: {0}")]
     MeiliError(#[from] meilisearch_sdk::errors::Error),
+
+    #[error("tantivy error: {0}")]
+    TantivyError(#[from] tantivy::TantivyError),
+
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("serialization error: {0}")]
+    SerializationError(#[from] serde_json::Error),
+
+    #[error("missing config value: {0}")]
+    MissingConfig(&'static str),
+
+    #[error("index {0} is missing settings")]
+    MisconfiguredIndex(String),
+
+    #[error("no staging index has been created")]
+    MissingStaging,
+
+    #[error("blocking task failed: {0}")]
+    TaskError(#[from] rocket::tokio::task::JoinError),
+
+    #[error("embedding error: {0}")]
+    EmbeddingError(#[from] EmbeddingError),
+
+    #[error("semantic search is not enabled on this server")]
+    SemanticSearchDisabled,
 }
@@ -0,0 +1,752 @@
+use super::errors::{DatabaseError, Result};
+use super::models::{
+    AnnotationsEntry, AnnotationsUpdate, IndexDatesEntry, RepositoryDocument, RequestCacheEntry,
+    ANNOTATIONS_KEY, EMBEDDER, INDEX_DATES_KEY, REQUEST_CACHE_KEY,
+};
+use super::{
+    embed_query, highlighted_text, semantic_ratio, top_facet_values, with_annotations,
+    RequestCache, SearchBackend, MAX_TOTAL_HITS,
+};
+use crate::embeddings::Embedder;
+use crate::metrics::METRICS;
+use meilisearch_sdk::client::{Client, SwapIndexes};
+use meilisearch_sdk::documents::DocumentsQuery;
+use meilisearch_sdk::errors::{Error as MeiliError, ErrorCode};
+use meilisearch_sdk::indexes::Index;
+use meilisearch_sdk::search::{MatchingStrategies, SearchResult, Selectors};
+use meilisearch_sdk::settings::{
+    Embedder as EmbedderSettings, EmbedderSource, FacetSortValue, FacetingSettings,
+    PaginationSetting,
+};
+use meilisearch_sdk::task_info::TaskInfo;
+use rocket::tokio::sync::Mutex;
+use serde::de::DeserializeOwned;
+use starsearch_sdk::models::{
+    Annotations, Facets, Filter, HighlightedText, Highlights, Id, IndexDates, Repository,
+    SearchMode, SearchOptions, SearchResponse, ServerInfo, Sort,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const REPOSITORIES_INDEX: &str = "repositories";
+const STAGING_INDEX: &str = "repositories_staging";
+const META_INDEX: &str = "meta";
+
+/// Time to wait for an index swap, which is only processed after all
+/// documents previously added to the staging index have been indexed.
+const SWAP_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// Markers wrapped around matched terms in formatted fields. Characters from
+/// the Unicode private use area are used, so they never clash with the
+/// contents of a README.
+const HIGHLIGHT_PRE_TAG: &str = "\u{e000}";
+const HIGHLIGHT_POST_TAG: &str = "\u{e001}";
+
+/// Number of words of the README passage returned with each hit.
+const README_SNIPPET_WORDS: usize = 30;
+
+/// Meilisearch only considers this many words of a search query.
+const MAX_QUERY_WORDS: usize = 10;
+
+/// Number of documents fetched at once when listing all of them.
+const LIST_PAGE_SIZE: usize = 1000;
+
+/// Attributes of which the facet distribution is requested.
+const FACET_ATTRIBUTES: [&str; 5] = [
+    "language",
+    "topics",
+    "license.spdx_id",
+    "owner.login",
+    "lists",
+];
+
+/// Attributes by which repositories can be filtered.
+const FILTERABLE_ATTRIBUTES: [&str; 10] = [
+    "language",
+    "topics",
+    "license.spdx_id",
+    "owner.login",
+    "lists",
+    "fork",
+    "archived",
+    "created_at_timestamp",
+    "updated_at_timestamp",
+    "starred_at_timestamp",
+];
+
+const SORTABLE_ATTRIBUTES: [&str; 1] = ["starred_at_timestamp"];
+
+pub struct MeilisearchBackend {
+    client: Client,
+    indexes: IndexNames,
+    embedder: Option<Arc<Embedder>>,
+    /// Serializes updates of the stored annotations.
+    annotations_lock: Mutex<()>,
+}
+
+impl MeilisearchBackend {
+    /// Connects to the Meilisearch instance at the given host. Indexes of
+    /// a namespace are kept apart from the ones of all other namespaces, so
+    /// that several backends can share an instance.
+    pub async fn new(
+        host: impl Into<String>,
+        api_key: Option<impl Into<String>>,
+        namespace: Option<&str>,
+        embedder: Option<Arc<Embedder>>,
+    ) -> Result<Self> {
+        let client = Client::new(host, api_key)?;
+        let db = Self {
+            client,
+            indexes: IndexNames::new(namespace),
+            embedder,
+            annotations_lock: Mutex::new(()),
+        };
+
+        let idx = db
+            .create_index_if_not_exists(&db.indexes.repositories, Some("id"))
+            .await?;
+        db.configure_repositories_index(&idx).await?;
+
+        db.create_index_if_not_exists(&db.indexes.meta, Some("id"))
+            .await?;
+
+        Ok(db)
+    }
+
+    pub async fn create_index_if_not_exists(
+        &self,
+        uid: &str,
+        primary_key: Option<&str>,
+    ) -> Result<Index> {
+        let result = self.client.get_index(uid).await;
+        match result {
+            Ok(index) => Ok(index),
+            Err(meilisearch_sdk::errors::Error::Meilisearch(err))
+                if err.error_code == ErrorCode::IndexNotFound =>
+            {
+                self.client.create_index(uid, primary_key).await?;
+                Ok(self.client.index(uid))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Applies the settings of the repositories index to the given index.
+    async fn configure_repositories_index(&self, idx: &Index) -> Result<()> {
+        configure_repositories_index(idx).await?;
+
+        if let Some(embedder) = &self.embedder {
+            let settings = EmbedderSettings {
+                source: EmbedderSource::UserProvided,
+                dimensions: Some(embedder.dimensions()),
+                ..Default::default()
+            };
+            idx.set_embedders(&HashMap::from([(EMBEDDER.into(), settings)]))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_meta<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: DeserializeOwned + 'static + Send + Sync,
+    {
+        let meta_idx = self.client.index(&self.indexes.meta);
+        match meta_idx.get_document(key).await {
+            Ok(doc) => Ok(Some(doc)),
+            Err(meilisearch_sdk::errors::Error::Meilisearch(err))
+                if err.error_code == ErrorCode::DocumentNotFound =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn set_annotations_entry(&self, annotations: &HashMap<u32, Annotations>) -> Result<()> {
+        let task = self
+            .client
+            .index(&self.indexes.meta)
+            .add_documents(&[AnnotationsEntry::from(annotations)], Some("id"))
+            .await?;
+        self.wait_for_task(task).await
+    }
+
+    /// Replaces the annotations of the repository with the given ID in the
+    /// given index. Repositories which are not in the index are skipped.
+    async fn annotate_in(
+        &self,
+        uid: &str,
+        id: u32,
+        annotations: Option<&Annotations>,
+    ) -> Result<()> {
+        let idx = self.client.index(uid);
+        match idx.get_document::<Id>(&id.to_string()).await {
+            Ok(_) => {}
+            Err(MeiliError::Meilisearch(err))
+                if err.error_code == ErrorCode::DocumentNotFound
+                    || err.error_code == ErrorCode::IndexNotFound =>
+            {
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        // Partial updates keep all other attributes, including the vectors.
+        let task = idx
+            .add_or_update(&[AnnotationsUpdate { id, annotations }], Some("id"))
+            .await?;
+        self.wait_for_task(task).await
+    }
+
+    /// Waits until the given task has been processed, so that subsequent
+    /// reads see its changes.
+    async fn wait_for_task(&self, task: TaskInfo) -> Result<()> {
+        let task = task.wait_for_completion(&self.client, None, None).await?;
+        if task.is_failure() {
+            return Err(MeiliError::Meilisearch(task.unwrap_failure()).into());
+        }
+        Ok(())
+    }
+}
+
+#[rocket::async_trait]
+impl SearchBackend for MeilisearchBackend {
+    async fn insert_repos(&self, repos: &[Repository]) -> Result<()> {
+        let repos = with_annotations(repos, &self.get_annotations().await?);
+        add_repositories(&self.client.index(&self.indexes.repositories), &repos).await
+    }
+
+    async fn remove(&self, ids: &[u32]) -> Result<()> {
+        let idx = self.client.index(&self.indexes.repositories);
+        idx.delete_documents(ids).await?;
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+        opts: &SearchOptions,
+    ) -> Result<SearchResponse> {
+        let started = Instant::now();
+        let vector = embed_query(self.embedder.as_deref(), opts.mode, query)?;
+
+        let idx = self.client.index(&self.indexes.repositories);
+
+        let mut search = idx.search();
+        search.with_query(query);
+
+        if let Some(vector) = &vector {
+            search
+                .with_hybrid(EMBEDDER, semantic_ratio(opts.mode))
+                .with_vector(vector);
+        }
+
+        let filter = build_filter(&opts.filter);
+        search.with_filter(&filter);
+
+        let sort = opts.sort.map(sort_expression);
+        if let Some(sort) = &sort {
+            search.with_sort(std::slice::from_ref(sort));
+        }
+
+        if opts.facets {
+            search.with_facets(Selectors::Some(&FACET_ATTRIBUTES));
+        }
+
+        search
+            .with_attributes_to_highlight(Selectors::Some(&["description", "readme_content"]))
+            .with_attributes_to_crop(Selectors::Some(&[(
+                "readme_content",
+                Some(README_SNIPPET_WORDS),
+            )]))
+            .with_highlight_pre_tag(HIGHLIGHT_PRE_TAG)
+            .with_highlight_post_tag(HIGHLIGHT_POST_TAG);
+
+        let res = search
+            .with_limit(limit)
+            .with_offset(offset)
+            .execute::<Repository>()
+            .await?;
+
+        let hits: Vec<_> = res.hits.into_iter().map(with_highlights).collect();
+        let total = res.estimated_total_hits.unwrap_or(offset + hits.len());
+        METRICS.observe_search("meilisearch", opts.mode, started.elapsed(), total);
+
+        Ok(SearchResponse {
+            total,
+            hits,
+            offset,
+            limit,
+            facets: res.facet_distribution.map(facets),
+        })
+    }
+
+    async fn list(
+        &self,
+        limit: usize,
+        offset: usize,
+        opts: &SearchOptions,
+    ) -> Result<SearchResponse> {
+        // The documents endpoint does not support sorting or facets, so such
+        // listings are performed as placeholder searches.
+        if opts.sort.is_some() || opts.facets {
+            return self.search("", limit, offset, opts).await;
+        }
+
+        let idx = self.client.index(&self.indexes.repositories);
+
+        let filter = build_filter(&opts.filter);
+
+        let res = DocumentsQuery::new(&idx)
+            .with_filter(&filter)
+            .with_limit(limit)
+            .with_offset(offset)
+            .execute()
+            .await?;
+
+        Ok(SearchResponse {
+            hits: res.results,
+            total: res.total as usize,
+            offset,
+            limit,
+            facets: None,
+        })
+    }
+
+    async fn list_ids(&self) -> Result<Vec<u32>> {
+        let idx = self.client.index(&self.indexes.repositories);
+
+        // Documents are only returned in pages, so they are fetched until a
+        // page comes back incomplete.
+        let mut ids = vec![];
+        loop {
+            let page = DocumentsQuery::new(&idx)
+                .with_fields(["id"])
+                .with_limit(LIST_PAGE_SIZE)
+                .with_offset(ids.len())
+                .execute::<Id>()
+                .await?
+                .results;
+            let count = page.len();
+            ids.extend(page.into_iter().map(|id| id.id));
+            if count < LIST_PAGE_SIZE {
+                return Ok(ids);
+            }
+        }
+    }
+
+    async fn get_repos(&self, ids: &[u32]) -> Result<Vec<Repository>> {
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let idx = self.client.index(&self.indexes.repositories);
+        let ids: Vec<_> = ids.iter().map(u32::to_string).collect();
+
+        let res = DocumentsQuery::new(&idx)
+            .with_ids(ids.iter().map(String::as_str))
+            .with_limit(ids.len())
+            .execute()
+            .await?
+            .results;
+
+        Ok(res)
+    }
+
+    async fn get_annotations(&self) -> Result<HashMap<u32, Annotations>> {
+        let entry: Option<AnnotationsEntry> = self.get_meta(ANNOTATIONS_KEY).await?;
+        Ok(entry.map(HashMap::from).unwrap_or_default())
+    }
+
+    async fn set_annotations(
+        &self,
+        id: u32,
+        annotations: Annotations,
+    ) -> Result<Option<Repository>> {
+        let _lock = self.annotations_lock.lock().await;
+
+        let Some(mut repo) = self.get_repos(&[id]).await?.pop() else {
+            return Ok(None);
+        };
+
+        let annotations = Some(annotations.normalized()).filter(|a| !a.is_empty());
+        let mut stored = self.get_annotations().await?;
+        match &annotations {
+            Some(annotations) => stored.insert(id, annotations.clone()),
+            None => stored.remove(&id),
+        };
+        self.set_annotations_entry(&stored).await?;
+
+        // A running full scrape might already have written the repository
+        // to the staging index.
+        self.annotate_in(&self.indexes.staging, id, annotations.as_ref())
+            .await?;
+        self.annotate_in(&self.indexes.repositories, id, annotations.as_ref())
+            .await?;
+
+        repo.annotations = annotations;
+        Ok(Some(repo))
+    }
+
+    async fn retain_annotations(&self, ids: &HashSet<u32>) -> Result<()> {
+        let _lock = self.annotations_lock.lock().await;
+
+        let mut stored = self.get_annotations().await?;
+        stored.retain(|id, _| ids.contains(id));
+        self.set_annotations_entry(&stored).await
+    }
+
+    async fn similar(&self, id: u32, limit: usize) -> Result<Option<Vec<Repository>>> {
+        let Some(repo) = self.get_repos(&[id]).await?.pop() else {
+            return Ok(None);
+        };
+
+        let embedding = match &self.embedder {
+            Some(embedder) => embedder.embed_repository(&repo)?,
+            None => None,
+        };
+
+        let query = similarity_query(&repo);
+        let idx = self.client.index(&self.indexes.repositories);
+
+        // Repositories sharing only some of the words are matched as well,
+        // dropping the most frequent words first.
+        let mut search = idx.search();
+        search
+            .with_query(&query)
+            .with_matching_strategy(MatchingStrategies::FREQUENCY)
+            .with_limit(limit.saturating_add(1));
+        if let Some(embedding) = &embedding {
+            search
+                .with_hybrid(EMBEDDER, semantic_ratio(SearchMode::Hybrid))
+                .with_vector(embedding);
+        }
+
+        let res = search.execute::<Repository>().await?;
+        let hits = res
+            .hits
+            .into_iter()
+            .map(|hit| hit.result)
+            .filter(|hit| hit.id != id)
+            .take(limit)
+            .collect();
+
+        Ok(Some(hits))
+    }
+
+    async fn create_staging(&self) -> Result<()> {
+        // Tasks are processed in order, so the index is re-created after the
+        // previous one has been deleted without waiting in between.
+        self.client.delete_index(&self.indexes.staging).await?;
+        self.client
+            .create_index(&self.indexes.staging, Some("id"))
+            .await?;
+        self.configure_repositories_index(&self.client.index(&self.indexes.staging))
+            .await
+    }
+
+    async fn insert_staging(&self, repos: &[Repository]) -> Result<()> {
+        let repos = with_annotations(repos, &self.get_annotations().await?);
+        add_repositories(&self.client.index(&self.indexes.staging), &repos).await
+    }
+
+    async fn swap_staging(&self) -> Result<()> {
+        let swap = SwapIndexes {
+            indexes: (
+                self.indexes.repositories.clone(),
+                self.indexes.staging.clone(),
+            ),
+            rename: None,
+        };
+
+        let task = self
+            .client
+            .swap_indexes([&swap])
+            .await?
+            .wait_for_completion(&self.client, None, Some(SWAP_TIMEOUT))
+            .await?;
+        if task.is_failure() {
+            return Err(meilisearch_sdk::errors::Error::Meilisearch(task.unwrap_failure()).into());
+        }
+
+        Ok(())
+    }
+
+    async fn get_index_dates(&self) -> Result<IndexDates> {
+        let index_dates = self.get_meta(INDEX_DATES_KEY).await?;
+        Ok(index_dates.unwrap_or_default())
+    }
+
+    async fn set_index_dates(&self, dates: IndexDates) -> Result<()> {
+        let meta_idx = self.client.index(&self.indexes.meta);
+        meta_idx
+            .add_documents(&[IndexDatesEntry::from(dates)], Some("id"))
+            .await?;
+        Ok(())
+    }
+
+    async fn get_request_cache(&self) -> Result<RequestCache> {
+        let cache: Option<RequestCacheEntry> = self.get_meta(REQUEST_CACHE_KEY).await?;
+        Ok(cache.map(RequestCache::from).unwrap_or_default())
+    }
+
+    async fn set_request_cache(&self, cache: &RequestCache) -> Result<()> {
+        let meta_idx = self.client.index(&self.indexes.meta);
+        meta_idx
+            .add_documents(&[RequestCacheEntry::from(cache)], Some("id"))
+            .await?;
+        Ok(())
+    }
+
+    async fn get_info(&self) -> Result<ServerInfo> {
+        let repo_idx = self.client.index(&self.indexes.repositories);
+        let stats = repo_idx.get_stats().await?;
+
+        let index_dates = self.get_index_dates().await?;
+
+        Ok(ServerInfo {
+            index_dates,
+            index_count: stats.number_of_documents,
+            semantic_search: self.semantic_search(),
+            users: vec![],
+        })
+    }
+
+    async fn check(&self) -> Result<()> {
+        self.client.health().await?;
+        self.client.get_index(&self.indexes.meta).await?;
+
+        let idx = self.client.get_index(&self.indexes.repositories).await?;
+        let filterable = idx.get_filterable_attributes().await?;
+        let sortable = idx.get_sortable_attributes().await?;
+        let configured = |attrs: &[&str], actual: &[String]| {
+            attrs.iter().all(|attr| actual.iter().any(|a| a == attr))
+        };
+        if !configured(&FILTERABLE_ATTRIBUTES, &filterable)
+            || !configured(&SORTABLE_ATTRIBUTES, &sortable)
+        {
+            return Err(DatabaseError::MisconfiguredIndex(
+                self.indexes.repositories.clone(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn semantic_search(&self) -> bool {
+        self.embedder.is_some()
+    }
+}
+
+/// UIDs of the indexes of a backend.
+struct IndexNames {
+    repositories: String,
+    staging: String,
+    meta: String,
+}
+
+impl IndexNames {
+    /// Returns the index names of the given namespace. Without a namespace,
+    /// the unprefixed names of earlier versions are used.
+    fn new(namespace: Option<&str>) -> Self {
+        let name = |base: &str| match namespace {
+            Some(namespace) => format!("{base}_{namespace}"),
+            None => base.to_string(),
+        };
+
+        Self {
+            repositories: name(REPOSITORIES_INDEX),
+            staging: name(STAGING_INDEX),
+            meta: name(META_INDEX),
+        }
+    }
+}
+
+async fn add_repositories(idx: &Index, repos: &[Repository]) -> Result<()> {
+    for reps in repos.chunks(5) {
+        let docs: Vec<_> = reps.iter().map(RepositoryDocument::from).collect();
+        idx.add_documents(&docs, Some("id")).await?;
+    }
+
+    Ok(())
+}
+
+/// Compiles the given filter into a Meilisearch filter expression. All
+/// user provided values are quoted.
+fn build_filter(filter: &Filter) -> String {
+    let mut filters = vec![];
+
+    if let Some(language) = &filter.language {
+        filters.push(format!("language = {}", quote(language)));
+    }
+    for topic in &filter.topics_all {
+        filters.push(format!("topics = {}", quote(topic)));
+    }
+    if !filter.topics_any.is_empty() {
+        let topics: Vec<_> = filter.topics_any.iter().map(|t| quote(t)).collect();
+        filters.push(format!("topics IN [{}]", topics.join(", ")));
+    }
+    if let Some(license) = &filter.license {
+        filters.push(format!("license.spdx_id = {}", quote(license)));
+    }
+    if let Some(owner) = &filter.owner {
+        filters.push(format!("owner.login = {}", quote(owner)));
+    }
+    for list in &filter.lists {
+        filters.push(format!("lists = {}", quote(list)));
+    }
+    if let Some(fork) = filter.fork {
+        filters.push(format!("fork = {fork}"));
+    }
+    if let Some(archived) = filter.archived {
+        filters.push(format!("archived = {archived}"));
+    }
+
+    let ranges = [
+        ("created_at_timestamp", &filter.created),
+        ("updated_at_timestamp", &filter.updated),
+        ("starred_at_timestamp", &filter.starred),
+    ];
+    for (attribute, range) in ranges {
+        if let Some(after) = range.after {
+            filters.push(format!("{attribute} >= {}", after.timestamp()));
+        }
+        if let Some(before) = range.before {
+            filters.push(format!("{attribute} <= {}", before.timestamp()));
+        }
+    }
+
+    filters.join(" AND ")
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Builds a search query from the topics, language and description of the
+/// given repository, in this order of precedence.
+fn similarity_query(repo: &Repository) -> String {
+    let topics = repo.topics.iter().flatten().flat_map(|t| t.split('-'));
+    let description = repo.description.iter().flat_map(|d| d.split_whitespace());
+
+    let mut words: Vec<&str> = vec![];
+    for word in topics.chain(repo.language.as_deref()).chain(description) {
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if word.chars().count() < 3 || words.iter().any(|w| w.eq_ignore_ascii_case(word)) {
+            continue;
+        }
+        words.push(word);
+        if words.len() == MAX_QUERY_WORDS {
+            break;
+        }
+    }
+
+    words.join(" ")
+}
+
+/// Moves the formatted fields of the given hit into its highlights.
+fn with_highlights(hit: SearchResult<Repository>) -> Repository {
+    let mut repo = hit.result;
+
+    let formatted = hit.formatted_result.unwrap_or_default();
+    let field = |name: &str| {
+        formatted
+            .get(name)
+            .and_then(|v| v.as_str())
+            .and_then(parse_formatted)
+    };
+
+    let highlights = Highlights {
+        description: field("description"),
+        readme: field("readme_content"),
+    };
+    repo.highlights = (!highlights.is_empty()).then_some(highlights);
+    repo
+}
+
+/// Parses a formatted field into its text and matches. Returns `None` if
+/// nothing in the field has been matched.
+fn parse_formatted(formatted: &str) -> Option<HighlightedText> {
+    let mut chunks = formatted.split(HIGHLIGHT_PRE_TAG);
+    let mut parts = vec![(chunks.next().unwrap_or_default(), false)];
+
+    for chunk in chunks {
+        match chunk.split_once(HIGHLIGHT_POST_TAG) {
+            Some((matched, rest)) => parts.extend([(matched, true), (rest, false)]),
+            None => parts.push((chunk, true)),
+        }
+    }
+
+    highlighted_text(parts)
+}
+
+fn facets(mut distribution: HashMap<String, HashMap<String, usize>>) -> Facets {
+    let mut values =
+        |attribute: &str| top_facet_values(distribution.remove(attribute).unwrap_or_default());
+
+    Facets {
+        language: values("language"),
+        topics: values("topics"),
+        license: values("license.spdx_id"),
+        owner: values("owner.login"),
+        lists: values("lists"),
+    }
+}
+
+fn sort_expression(sort: Sort) -> &'static str {
+    match sort {
+        Sort::StarredAtDesc => "starred_at_timestamp:desc",
+        Sort::StarredAtAsc => "starred_at_timestamp:asc",
+    }
+}
+
+/// Applies the settings of the repositories index which do not depend on the
+/// embedder to the given index.
+async fn configure_repositories_index(idx: &Index) -> Result<()> {
+    idx.set_searchable_attributes([
+        "name",
+        "full_name",
+        "description",
+        "topics",
+        "language",
+        "readme_content",
+        "annotations.tags",
+        "annotations.note",
+    ])
+    .await?;
+
+    idx.set_filterable_attributes(FILTERABLE_ATTRIBUTES).await?;
+
+    idx.set_sortable_attributes(SORTABLE_ATTRIBUTES).await?;
+
+    idx.set_pagination(PaginationSetting {
+        max_total_hits: MAX_TOTAL_HITS,
+    })
+    .await?;
+
+    // Return the most frequent instead of the alphabetically first values
+    // of facets with many distinct values.
+    idx.set_faceting(&FacetingSettings {
+        max_values_per_facet: 100,
+        sort_facet_values_by: Some(BTreeMap::from([("*".into(), FacetSortValue::Count)])),
+    })
+    .await?;
+
+    idx.set_ranking_rules([
+        "words",
+        "typo",
+        "proximity",
+        "attribute",
+        "sort",
+        "exactness",
+        "updated_at:desc",
+    ])
+    .await?;
+
+    Ok(())
+}
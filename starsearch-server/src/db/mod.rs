@@ -1,184 +1,395 @@
 pub mod errors;
+mod meilisearch;
 mod models;
+mod tantivy;
 
-use errors::Result;
-use meilisearch_sdk::client::Client;
-use meilisearch_sdk::documents::DocumentsQuery;
-use meilisearch_sdk::errors::ErrorCode;
-use meilisearch_sdk::indexes::Index;
-use models::IndexDatesEntry;
-use starsearch_sdk::models::{Id, IndexDates, Repository, ServerInfo};
+pub use self::meilisearch::MeilisearchBackend;
+pub use self::models::{CachedResponse, RequestCache};
+pub use self::tantivy::TantivyBackend;
+use crate::config::{Backend, Config};
+use crate::embeddings::Embedder;
+use errors::{DatabaseError, Result};
+use meilisearch_sdk::errors::Error as MeiliError;
+pub use starsearch_sdk::models::MAX_TOTAL_HITS;
+use starsearch_sdk::models::{
+    Annotations, FacetCount, Filter, HighlightedText, IndexDates, MatchRange, Repository,
+    SearchMode, SearchOptions, SearchResponse, ServerInfo,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-pub struct Database {
-    client: Client,
-}
+/// Directory below the data directory holding the data of each namespace.
+const NAMESPACES_DIR: &str = "users";
 
-impl Database {
-    pub async fn new(host: impl Into<String>, api_key: Option<impl Into<String>>) -> Result<Self> {
-        let client = Client::new(host, api_key)?;
-        let db = Self { client };
+/// Delay before the second attempt to connect to Meilisearch, which may
+/// still be starting up alongside the server. It doubles with every further
+/// attempt up to the maximum.
+const CONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_CONNECT_DELAY: Duration = Duration::from_secs(30);
 
-        let idx = db
-            .create_index_if_not_exists("repositories", Some("id"))
-            .await?;
+/// Maximum number of values returned per facet.
+const MAX_FACET_VALUES: usize = 20;
 
-        db.create_index_if_not_exists("meta", Some("id")).await?;
+/// Number of repositories of an owner fetched at once when looking up a
+/// repository by its name.
+const LOOKUP_PAGE_SIZE: usize = 100;
 
-        idx.set_searchable_attributes([
-            "name",
-            "full_name",
-            "description",
-            "topics",
-            "language",
-            "readme_content",
-        ])
-        .await?;
-
-        idx.set_filterable_attributes(["language"]).await?;
-
-        idx.set_ranking_rules([
-            "words",
-            "typo",
-            "proximity",
-            "attribute",
-            "sort",
-            "exactness",
-            "updated_at:desc",
-        ])
-        .await?;
+/// Storage and full-text search of scraped repositories and index metadata.
+#[rocket::async_trait]
+pub trait SearchBackend: Send + Sync {
+    async fn insert_repos(&self, repos: &[Repository]) -> Result<()>;
 
-        Ok(db)
-    }
+    /// Searches the index. Hits carry the passages of their description and
+    /// README matching the query. If requested in the options, the response
+    /// also holds the facet distribution of all matching repositories.
+    async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+        opts: &SearchOptions,
+    ) -> Result<SearchResponse>;
 
-    pub async fn create_index_if_not_exists(
+    async fn list(
         &self,
-        uid: &str,
-        primary_key: Option<&str>,
-    ) -> Result<Index> {
-        let result = self.client.get_index(uid).await;
-        match result {
-            Ok(index) => Ok(index),
-            Err(meilisearch_sdk::errors::Error::Meilisearch(err))
-                if err.error_code == ErrorCode::IndexNotFound =>
-            {
-                self.client.create_index(uid, primary_key).await?;
-                Ok(self.client.index(uid))
+        limit: usize,
+        offset: usize,
+        opts: &SearchOptions,
+    ) -> Result<SearchResponse>;
+
+    async fn list_ids(&self) -> Result<Vec<u32>>;
+
+    /// Returns the stored repositories with the given IDs. IDs which are not
+    /// present in the index are skipped.
+    async fn get_repos(&self, ids: &[u32]) -> Result<Vec<Repository>>;
+
+    /// Removes the repositories with the given IDs from the live index. Full
+    /// scrapes drop unstarred repositories by swapping in a staging index, so
+    /// this is only needed for single deletions.
+    async fn remove(&self, ids: &[u32]) -> Result<()>;
+
+    /// Returns the stored repository with the given owner and name, which are
+    /// matched case-insensitively like on GitHub.
+    async fn get_repo_by_name(&self, owner: &str, name: &str) -> Result<Option<Repository>> {
+        let opts = SearchOptions {
+            filter: Filter {
+                owner: Some(owner.into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut offset = 0;
+        loop {
+            let res = self.list(LOOKUP_PAGE_SIZE, offset, &opts).await?;
+            let done = res.hits.len() < LOOKUP_PAGE_SIZE;
+
+            let found = res
+                .hits
+                .into_iter()
+                .find(|r| r.name.eq_ignore_ascii_case(name));
+            if found.is_some() || done {
+                return Ok(found);
             }
-            Err(err) => Err(err.into()),
+
+            offset += LOOKUP_PAGE_SIZE;
         }
     }
 
-    pub async fn insert_repos(&self, repos: &[Repository]) -> Result<()> {
-        let idx = self.client.index("repositories");
-        for reps in repos.chunks(5) {
-            idx.add_documents(reps, Some("id")).await?;
+    /// Returns the stored annotations of all repositories by their IDs.
+    async fn get_annotations(&self) -> Result<HashMap<u32, Annotations>>;
+
+    /// Stores the annotations of the repository with the given ID and updates
+    /// the indexed repository, so that they are searchable. Empty annotations
+    /// are removed. Returns `None` if the repository is not in the index.
+    async fn set_annotations(
+        &self,
+        id: u32,
+        annotations: Annotations,
+    ) -> Result<Option<Repository>>;
+
+    /// Removes the stored annotations of all repositories except the ones
+    /// with the given IDs.
+    async fn retain_annotations(&self, ids: &HashSet<u32>) -> Result<()>;
+
+    /// Returns the repositories most similar to the one with the given ID by
+    /// shared topics, language, the text of the name, description and README
+    /// and, if semantic search is enabled, their embeddings. Returns `None` if
+    /// the repository is not in the index.
+    async fn similar(&self, id: u32, limit: usize) -> Result<Option<Vec<Repository>>>;
+
+    /// Creates an empty staging index, replacing a previously existing one.
+    /// Full scrapes are written into the staging index so that the live
+    /// index stays untouched until the scrape has finished.
+    async fn create_staging(&self) -> Result<()>;
+
+    async fn insert_staging(&self, repos: &[Repository]) -> Result<()>;
+
+    /// Atomically swaps the staging index with the live index. Afterwards,
+    /// the staging index holds the previous live index, which is kept for
+    /// rollback until the next staging index is created.
+    async fn swap_staging(&self) -> Result<()>;
+
+    async fn get_index_dates(&self) -> Result<IndexDates>;
+
+    async fn set_index_dates(&self, dates: IndexDates) -> Result<()>;
+
+    async fn get_request_cache(&self) -> Result<RequestCache>;
+
+    async fn set_request_cache(&self, cache: &RequestCache) -> Result<()>;
+
+    async fn get_info(&self) -> Result<ServerInfo>;
+
+    /// Checks that the backend is reachable and that its indexes exist with
+    /// the expected settings.
+    async fn check(&self) -> Result<()>;
+
+    /// Whether the semantic and hybrid search modes are available.
+    fn semantic_search(&self) -> bool;
+}
+
+/// Creates the search backend selected in the given config. The data of a
+/// namespace is stored apart from the data of all other namespaces. Semantic
+/// search is only available if an embedder is given.
+///
+/// With `retry` set, connecting to Meilisearch is retried while it is
+/// unreachable until the configured connect timeout has passed. Errors
+/// reported by Meilisearch itself, like an invalid API key, fail right away.
+pub async fn connect(
+    cfg: &Config,
+    namespace: Option<&str>,
+    embedder: Option<Arc<Embedder>>,
+    retry: bool,
+) -> Result<Arc<dyn SearchBackend>> {
+    match cfg.search_backend.unwrap_or_default() {
+        Backend::Meilisearch => {
+            let url = cfg
+                .meilisearch_url
+                .as_ref()
+                .ok_or(DatabaseError::MissingConfig("meilisearch_url"))?;
+            let deadline = cfg
+                .meilisearch_connect_timeout_seconds
+                .map(|timeout| Instant::now() + Duration::from_secs(timeout));
+            let mut delay = CONNECT_DELAY;
+            loop {
+                let res = MeilisearchBackend::new(
+                    url,
+                    cfg.meilisearch_apikey.as_ref(),
+                    namespace,
+                    embedder.clone(),
+                )
+                .await;
+                match res {
+                    Err(DatabaseError::MeiliError(
+                        err @ (MeiliError::HttpError(_)
+                        | MeiliError::Timeout
+                        | MeiliError::MeilisearchCommunication(_)),
+                    )) if retry && deadline.is_none_or(|d| Instant::now() + delay < d) => {
+                        warn!(
+                            "Connecting to Meilisearch failed: {err}; retrying in {} seconds",
+                            delay.as_secs()
+                        );
+                        rocket::tokio::time::sleep(delay).await;
+                        delay = (delay * 2).min(MAX_CONNECT_DELAY);
+                    }
+                    res => return Ok(Arc::new(res?)),
+                }
+            }
         }
+        Backend::Tantivy => {
+            let mut data_dir = PathBuf::from(cfg.data_dir());
+            if let Some(namespace) = namespace {
+                data_dir = data_dir.join(NAMESPACES_DIR).join(namespace);
+            }
+            let backend = TantivyBackend::open(data_dir, embedder)?;
+            Ok(Arc::new(backend))
+        }
+    }
+}
 
-        Ok(())
+/// Returns the given repositories with their stored annotations attached.
+/// Annotations are only stored separately, so that scrapes never overwrite
+/// them.
+fn with_annotations(
+    repos: &[Repository],
+    annotations: &HashMap<u32, Annotations>,
+) -> Vec<Repository> {
+    repos
+        .iter()
+        .cloned()
+        .map(|mut repo| {
+            repo.annotations = annotations.get(&repo.id).cloned();
+            repo
+        })
+        .collect()
+}
+
+/// Returns the weight of the semantic similarity in the ranking of the given
+/// search mode.
+fn semantic_ratio(mode: SearchMode) -> f32 {
+    match mode {
+        SearchMode::Keyword => 0.0,
+        SearchMode::Semantic => 1.0,
+        SearchMode::Hybrid => 0.5,
     }
+}
 
-    pub async fn search(
-        &self,
-        query: &str,
-        limit: usize,
-        language: Option<&str>,
-    ) -> Result<Vec<Repository>> {
-        let idx = self.client.index("repositories");
-
-        let mut search = idx.search();
-        search.with_query(query);
-
-        let filter = language
-            .map(|v| format!("language = {v}"))
-            .unwrap_or_else(|| "".into());
-        search.with_filter(&filter);
-
-        let res: Vec<_> = search
-            .with_limit(limit)
-            .execute::<Repository>()
-            .await?
-            .hits
-            .iter()
-            .map(|r| &r.result)
-            .cloned()
-            .collect();
-
-        Ok(res)
+/// Embeds the query of a search in the given mode. Returns `None` for
+/// keyword searches and queries without any known terms, which are
+/// performed as keyword searches.
+fn embed_query(
+    embedder: Option<&Embedder>,
+    mode: SearchMode,
+    query: &str,
+) -> Result<Option<Vec<f32>>> {
+    if mode == SearchMode::Keyword {
+        return Ok(None);
     }
 
-    pub async fn list(&self, limit: usize, language: Option<&str>) -> Result<Vec<Repository>> {
-        let idx = self.client.index("repositories");
+    let embedder = embedder.ok_or(DatabaseError::SemanticSearchDisabled)?;
+    Ok(embedder.embed(query)?)
+}
+
+/// Returns the most frequent facet values ordered by descending count and
+/// value.
+pub fn top_facet_values(counts: impl IntoIterator<Item = (String, usize)>) -> Vec<FacetCount> {
+    let mut values: Vec<_> = counts
+        .into_iter()
+        .map(|(value, count)| FacetCount { value, count })
+        .collect();
+    values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    values.truncate(MAX_FACET_VALUES);
+    values
+}
 
-        let filter = language
-            .map(|v| format!("language = {v}"))
-            .unwrap_or_else(|| "".into());
+/// Builds a highlighted text from consecutive parts, each flagged whether it
+/// is a match. Whitespace is collapsed, so that passages of READMEs can be
+/// shown on a single line. Returns `None` if there is no match.
+fn highlighted_text<'a>(
+    parts: impl IntoIterator<Item = (&'a str, bool)>,
+) -> Option<HighlightedText> {
+    let mut text = String::new();
+    let mut matches = vec![];
 
-        let res = DocumentsQuery::new(&idx)
-            .with_filter(&filter)
-            .with_limit(limit)
-            .execute()
-            .await?
-            .results;
+    let space = |text: &mut String| {
+        if !text.is_empty() && !text.ends_with(' ') {
+            text.push(' ');
+        }
+    };
 
-        Ok(res)
-    }
+    for (part, matched) in parts {
+        if part.starts_with(char::is_whitespace) {
+            space(&mut text);
+        }
 
-    pub async fn list_ids<T>(&self) -> Result<T>
-    where
-        T: FromIterator<u32>,
-    {
-        let idx = self.client.index("repositories");
-
-        let res = DocumentsQuery::new(&idx)
-            .with_fields(["id"])
-            .execute::<Id>()
-            .await?
-            .results
-            .into_iter()
-            .map(|id| id.id)
-            .collect();
-
-        Ok(res)
+        let start = text.len();
+        for (i, word) in part.split_whitespace().enumerate() {
+            if i > 0 {
+                text.push(' ');
+            }
+            text.push_str(word);
+        }
+        if matched && text.len() > start {
+            matches.push(MatchRange {
+                start,
+                length: text.len() - start,
+            });
+        }
+
+        if part.ends_with(char::is_whitespace) {
+            space(&mut text);
+        }
     }
 
-    pub async fn remove(&self, ids: &[u32]) -> Result<()> {
-        let idx = self.client.index("repositories");
-        idx.delete_documents(ids).await?;
-        Ok(())
+    if matches.is_empty() {
+        return None;
     }
 
-    pub async fn get_index_dates(&self) -> Result<IndexDates> {
-        let meta_idx = self.client.index("meta");
-        let index_dates = match meta_idx.get_document("index_dates").await {
-            Ok(doc) => doc,
-            Err(meilisearch_sdk::errors::Error::Meilisearch(err))
-                if err.error_code == ErrorCode::DocumentNotFound =>
-            {
-                IndexDates::default()
-            }
+    text.truncate(text.trim_end().len());
+    Some(HighlightedText { text, matches })
+}
 
-            Err(err) => return Err(err.into()),
-        };
-        Ok(index_dates)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlighted_text_collapses_whitespace() {
+        let res = highlighted_text([
+            ("A  fast\n\n", false),
+            ("search", true),
+            (" engine ", false),
+        ])
+        .unwrap();
+        assert_eq!(res.text, "A fast search engine");
+        assert_eq!(
+            res.matches,
+            [MatchRange {
+                start: 7,
+                length: 6
+            }]
+        );
     }
 
-    pub async fn set_index_dates(&self, dates: IndexDates) -> Result<()> {
-        let meta_idx = self.client.index("meta");
-        meta_idx
-            .add_documents(&[IndexDatesEntry::from(dates)], Some("id"))
-            .await?;
-        Ok(())
+    #[test]
+    fn highlighted_text_keeps_adjacent_parts_together() {
+        let res = highlighted_text([("re", false), ("search", true), ("ed", false)]).unwrap();
+        assert_eq!(res.text, "researched");
+        assert_eq!(
+            res.matches,
+            [MatchRange {
+                start: 2,
+                length: 6
+            }]
+        );
     }
 
-    pub async fn get_info(&self) -> Result<ServerInfo> {
-        let repo_idx = self.client.index("repositories");
-        let stats = repo_idx.get_stats().await?;
+    #[test]
+    fn highlighted_text_without_matches() {
+        assert!(highlighted_text([("text", false), (" ", true)]).is_none());
+    }
 
-        let index_dates = self.get_index_dates().await?;
+    fn meilisearch_config(url: &str) -> Config {
+        let mut cfg = crate::testing::config(std::path::Path::new("."));
+        cfg.search_backend = Some(Backend::Meilisearch);
+        cfg.meilisearch_url = Some(url.into());
+        cfg
+    }
 
-        Ok(ServerInfo {
-            index_dates,
-            index_count: stats.number_of_documents,
+    #[rocket::async_test]
+    async fn connect_fails_on_rejected_api_key() {
+        // Rejects every request like Meilisearch does with a wrong key.
+        let url = crate::testing::http_server(|_, _| {
+            let body = r#"{"message":"The provided API key is invalid.","code":"invalid_api_key","type":"auth","link":""}"#;
+            (403, body.into())
         })
+        .await;
+
+        let res = rocket::tokio::time::timeout(
+            Duration::from_secs(5),
+            connect(&meilisearch_config(&url), None, None, true),
+        )
+        .await
+        .expect("rejected API keys are not retried");
+        assert!(matches!(
+            res,
+            Err(DatabaseError::MeiliError(MeiliError::Meilisearch(_)))
+        ));
+    }
+
+    #[rocket::async_test]
+    async fn connect_retries_unreachable_meilisearch() {
+        let mut cfg = meilisearch_config("http://127.0.0.1:9");
+        cfg.meilisearch_connect_timeout_seconds = Some(2);
+
+        let started = Instant::now();
+        let res = connect(&cfg, None, None, true).await;
+        assert!(matches!(
+            res,
+            Err(DatabaseError::MeiliError(MeiliError::HttpError(_)))
+        ));
+        assert!(started.elapsed() >= CONNECT_DELAY);
     }
 }
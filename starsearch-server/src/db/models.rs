@@ -1,8 +1,15 @@
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
-use starsearch_sdk::models::IndexDates;
+use starsearch_sdk::models::{Annotations, IndexDates, Repository};
+use std::collections::{HashMap, HashSet};
 
 pub const INDEX_DATES_KEY: &str = "index_dates";
+pub const REQUEST_CACHE_KEY: &str = "request_cache";
+pub const LIVE_INDEX_KEY: &str = "live_index";
+pub const ANNOTATIONS_KEY: &str = "annotations";
+
+/// Name of the Meilisearch embedder of the vectors computed by the scraper.
+pub const EMBEDDER: &str = "default";
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct IndexDatesEntry {
@@ -20,3 +27,132 @@ impl From<IndexDates> for IndexDatesEntry {
         }
     }
 }
+
+/// A repository as stored in the Meilisearch index.
+///
+/// Meilisearch can only apply range filters and sorting on numeric values, so
+/// dates are additionally stored as UNIX timestamps. Embeddings are passed as
+/// user provided vectors.
+#[derive(Serialize, Clone, Debug)]
+pub struct RepositoryDocument<'a> {
+    #[serde(flatten)]
+    repository: &'a Repository,
+    created_at_timestamp: i64,
+    updated_at_timestamp: i64,
+    starred_at_timestamp: Option<i64>,
+    #[serde(rename = "_vectors", skip_serializing_if = "Option::is_none")]
+    vectors: Option<HashMap<&'static str, &'a [f32]>>,
+}
+
+impl<'a> From<&'a Repository> for RepositoryDocument<'a> {
+    fn from(value: &'a Repository) -> Self {
+        Self {
+            repository: value,
+            created_at_timestamp: value.created_at.timestamp(),
+            updated_at_timestamp: value.updated_at.timestamp(),
+            starred_at_timestamp: value.starred_at.map(|v| v.timestamp()),
+            vectors: value
+                .embedding
+                .as_deref()
+                .map(|v| HashMap::from([(EMBEDDER, v)])),
+        }
+    }
+}
+
+/// ETags of previously performed GitHub requests used to send conditional
+/// requests on subsequent scrapes.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct RequestCache {
+    entries: HashMap<String, CachedResponse>,
+    #[serde(skip)]
+    used: HashSet<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CachedResponse {
+    pub etag: String,
+    /// IDs of the repositories contained in a cached starred repositories
+    /// page.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ids: Vec<u32>,
+}
+
+impl RequestCache {
+    pub fn get(&mut self, key: &str) -> Option<&CachedResponse> {
+        self.used.insert(key.into());
+        self.entries.get(key)
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, response: CachedResponse) {
+        let key = key.into();
+        self.used.insert(key.clone());
+        self.entries.insert(key, response);
+    }
+
+    /// Removes all entries which have not been accessed since the cache has
+    /// been loaded.
+    pub fn retain_used(&mut self) {
+        self.entries.retain(|k, _| self.used.contains(k));
+    }
+}
+
+/// Meilisearch document representation of the [`RequestCache`].
+///
+/// Entries are stored as a list instead of a map so that Meilisearch does not
+/// create an attribute for every cached URL.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RequestCacheEntry {
+    id: String,
+    entries: Vec<(String, CachedResponse)>,
+}
+
+impl From<&RequestCache> for RequestCacheEntry {
+    fn from(value: &RequestCache) -> Self {
+        Self {
+            id: REQUEST_CACHE_KEY.into(),
+            entries: value
+                .entries
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl From<RequestCacheEntry> for RequestCache {
+    fn from(value: RequestCacheEntry) -> Self {
+        Self {
+            entries: value.entries.into_iter().collect(),
+            used: HashSet::new(),
+        }
+    }
+}
+
+/// Meilisearch document holding the annotations of all repositories.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct AnnotationsEntry {
+    id: String,
+    entries: Vec<(u32, Annotations)>,
+}
+
+impl From<&HashMap<u32, Annotations>> for AnnotationsEntry {
+    fn from(value: &HashMap<u32, Annotations>) -> Self {
+        Self {
+            id: ANNOTATIONS_KEY.into(),
+            entries: value.iter().map(|(k, v)| (*k, v.clone())).collect(),
+        }
+    }
+}
+
+impl From<AnnotationsEntry> for HashMap<u32, Annotations> {
+    fn from(value: AnnotationsEntry) -> Self {
+        value.entries.into_iter().collect()
+    }
+}
+
+/// Partial update of the annotations of an indexed repository.
+#[derive(Serialize, Clone, Debug)]
+pub struct AnnotationsUpdate<'a> {
+    pub id: u32,
+    pub annotations: Option<&'a Annotations>,
+}
@@ -0,0 +1,1469 @@
+use super::errors::{DatabaseError, Result};
+use super::models::{ANNOTATIONS_KEY, INDEX_DATES_KEY, LIVE_INDEX_KEY, REQUEST_CACHE_KEY};
+use super::{
+    embed_query, highlighted_text, semantic_ratio, top_facet_values, with_annotations,
+    RequestCache, SearchBackend, MAX_FACET_VALUES,
+};
+use crate::embeddings::{self, Embedder};
+use crate::files;
+use crate::metrics::METRICS;
+use rocket::tokio::sync::Mutex as AsyncMutex;
+use rocket::tokio::task;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use starsearch_sdk::models::{
+    Annotations, Facets, Filter, HighlightedText, Highlights, IndexDates, Repository, SearchMode,
+    SearchOptions, SearchResponse, ServerInfo, Sort,
+};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tantivy::aggregation::agg_req::Aggregations;
+use tantivy::aggregation::agg_result::{AggregationResult, BucketResult};
+use tantivy::aggregation::{AggregationCollector, Key};
+use tantivy::collector::{Count, DocSetCollector, TopDocs};
+use tantivy::columnar::BytesColumn;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, MoreLikeThisQuery, Occur, Query, QueryParser, RangeQuery,
+    TermQuery, TermSetQuery,
+};
+use tantivy::schema::{
+    Field, IndexRecordOption, OwnedValue, Schema, TextFieldIndexing, TextOptions, Value, FAST,
+    INDEXED, STORED, TEXT,
+};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::tokenizer::{LowerCaser, RawTokenizer, TextAnalyzer, TokenStream};
+use tantivy::{
+    DateTime, DocAddress, DocId, Index, IndexReader, IndexWriter, Order, ReloadPolicy, Searcher,
+    TantivyDocument, TantivyError, Term,
+};
+
+const WRITER_MEMORY_BUDGET: usize = 50_000_000;
+
+/// Tokenizer of the exact match filter fields, which indexes the whole value
+/// lowercased. The original values are kept in the fast fields for facets.
+const KEY_TOKENIZER: &str = "key";
+
+/// Maximum number of characters of the passages returned with each hit. The
+/// description is returned whole, the limit only selects the matches.
+const DESCRIPTION_SNIPPET_CHARS: usize = 150;
+const README_SNIPPET_CHARS: usize = 200;
+
+/// Boosts of the shared attributes when looking for similar repositories.
+/// The text similarity of the name, description and README has a boost of 1.
+const SIMILAR_TOPIC_BOOST: f32 = 2.0;
+const SIMILAR_LANGUAGE_BOOST: f32 = 1.5;
+
+/// Maximum number of terms of the description and README which are used to
+/// find similar repositories.
+const SIMILAR_MAX_TERMS: usize = 30;
+
+/// Names of the two index directories. One of them holds the live index and
+/// the other one either the staging or the previous index.
+const INDEX_DIRS: [&str; 2] = ["repositories", "repositories_staging"];
+
+/// Embedded search backend storing the index and metadata in a local
+/// data directory.
+pub struct TantivyBackend {
+    data_dir: PathBuf,
+    schema: Schema,
+    fields: Fields,
+    live: RwLock<Arc<IndexHandle>>,
+    staging: Mutex<Option<Arc<IndexHandle>>>,
+    meta: Arc<MetaStore>,
+    embedder: Option<Arc<Embedder>>,
+    /// Serializes updates of the stored annotations.
+    annotations_lock: AsyncMutex<()>,
+}
+
+struct IndexHandle {
+    dir: &'static str,
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+}
+
+impl IndexHandle {
+    fn open(data_dir: &Path, dir: &'static str, schema: Schema) -> Result<Self> {
+        let index = open_index(&data_dir.join(dir), schema)?;
+        index.tokenizers().register(
+            KEY_TOKENIZER,
+            TextAnalyzer::builder(RawTokenizer::default())
+                .filter(LowerCaser)
+                .build(),
+        );
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let writer = index.writer(WRITER_MEMORY_BUDGET)?;
+
+        Ok(Self {
+            dir,
+            index,
+            reader,
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Deletes the documents of the repositories with the given IDs and
+    /// commits the deletion.
+    fn delete(&self, fields: &Fields, ids: &[u32]) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        for id in ids {
+            writer.delete_term(Term::from_field_u64(fields.id, (*id).into()));
+        }
+        writer.commit()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+
+    /// Replaces the documents of the given repositories and commits them.
+    /// Blocks until the segments have been written.
+    fn insert(&self, fields: &Fields, repos: &[Repository]) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        for repo in repos {
+            writer.delete_term(Term::from_field_u64(fields.id, repo.id.into()));
+            writer.add_document(fields.document(repo)?)?;
+        }
+        writer.commit()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct Fields {
+    id: Field,
+    name: Field,
+    full_name: Field,
+    description: Field,
+    topics: Field,
+    language: Field,
+    language_key: Field,
+    topic_keys: Field,
+    license_key: Field,
+    owner_key: Field,
+    list_keys: Field,
+    fork: Field,
+    archived: Field,
+    readme_content: Field,
+    note: Field,
+    tags: Field,
+    created_at: Field,
+    updated_at: Field,
+    starred_at: Field,
+    embedding: Field,
+    source: Field,
+}
+
+impl Fields {
+    fn schema() -> (Schema, Self) {
+        let key = TextOptions::default()
+            .set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(KEY_TOKENIZER)
+                    .set_index_option(IndexRecordOption::Basic),
+            )
+            .set_fast(None);
+
+        let mut builder = Schema::builder();
+        let fields = Self {
+            id: builder.add_u64_field("id", INDEXED | STORED | FAST),
+            name: builder.add_text_field("name", TEXT),
+            full_name: builder.add_text_field("full_name", TEXT),
+            description: builder.add_text_field("description", TEXT),
+            topics: builder.add_text_field("topics", TEXT),
+            language: builder.add_text_field("language", TEXT),
+            language_key: builder.add_text_field("language_key", key.clone()),
+            topic_keys: builder.add_text_field("topic_keys", key.clone()),
+            license_key: builder.add_text_field("license_key", key.clone()),
+            owner_key: builder.add_text_field("owner_key", key.clone()),
+            list_keys: builder.add_text_field("list_keys", key.clone()),
+            fork: builder.add_bool_field("fork", INDEXED),
+            archived: builder.add_bool_field("archived", INDEXED),
+            readme_content: builder.add_text_field("readme_content", TEXT),
+            note: builder.add_text_field("note", TEXT),
+            tags: builder.add_text_field("tags", TEXT),
+            created_at: builder.add_date_field("created_at", INDEXED | FAST),
+            updated_at: builder.add_date_field("updated_at", INDEXED | FAST),
+            starred_at: builder.add_date_field("starred_at", INDEXED | FAST),
+            embedding: builder.add_bytes_field("embedding", FAST),
+            source: builder.add_text_field("source", STORED),
+        };
+        (builder.build(), fields)
+    }
+
+    fn document(&self, repo: &Repository) -> Result<TantivyDocument> {
+        let f = self;
+        let mut doc = TantivyDocument::default();
+
+        doc.add_u64(f.id, repo.id.into());
+        doc.add_text(f.name, &repo.name);
+        doc.add_text(f.full_name, &repo.full_name);
+        if let Some(description) = &repo.description {
+            doc.add_text(f.description, description);
+        }
+        for topic in repo.topics.iter().flatten() {
+            doc.add_text(f.topics, topic);
+            doc.add_text(f.topic_keys, topic);
+        }
+        if let Some(language) = &repo.language {
+            doc.add_text(f.language, language);
+            doc.add_text(f.language_key, language);
+        }
+        if let Some(spdx_id) = repo.license.as_ref().and_then(|l| l.spdx_id.as_ref()) {
+            doc.add_text(f.license_key, spdx_id);
+        }
+        doc.add_text(f.owner_key, &repo.owner.login);
+        for list in &repo.lists {
+            doc.add_text(f.list_keys, list);
+        }
+        doc.add_bool(f.fork, repo.fork);
+        doc.add_bool(f.archived, repo.archived);
+        if let Some(readme_content) = &repo.readme_content {
+            doc.add_text(f.readme_content, readme_content);
+        }
+        if let Some(annotations) = &repo.annotations {
+            if let Some(note) = &annotations.note {
+                doc.add_text(f.note, note);
+            }
+            for tag in &annotations.tags {
+                doc.add_text(f.tags, tag);
+            }
+        }
+        doc.add_date(f.created_at, to_date(&repo.created_at));
+        doc.add_date(f.updated_at, to_date(&repo.updated_at));
+        if let Some(starred_at) = &repo.starred_at {
+            doc.add_date(f.starred_at, to_date(starred_at));
+        }
+        if let Some(embedding) = &repo.embedding {
+            let bytes: Vec<_> = embedding.iter().flat_map(|v| v.to_le_bytes()).collect();
+            doc.add_bytes(f.embedding, &bytes);
+        }
+        doc.add_text(f.source, serde_json::to_string(repo)?);
+
+        Ok(doc)
+    }
+
+    fn read_document(&self, doc: &TantivyDocument) -> Result<Repository> {
+        let source = doc
+            .get_first(self.source)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        Ok(serde_json::from_str(source)?)
+    }
+
+    fn fetch_docs(&self, searcher: &Searcher, addrs: &[DocAddress]) -> Result<Vec<Repository>> {
+        addrs
+            .iter()
+            .map(|addr| self.read_document(&searcher.doc(*addr)?))
+            .collect()
+    }
+}
+
+impl TantivyBackend {
+    pub fn open(data_dir: impl AsRef<Path>, embedder: Option<Arc<Embedder>>) -> Result<Self> {
+        let data_dir = data_dir.as_ref();
+        let (schema, fields) = Fields::schema();
+
+        let meta = MetaStore::open(data_dir.join("meta.json"))?;
+        let live_dir: Option<String> = meta.get(LIVE_INDEX_KEY)?;
+        let live_dir = INDEX_DIRS
+            .into_iter()
+            .find(|dir| Some(*dir) == live_dir.as_deref())
+            .unwrap_or(INDEX_DIRS[0]);
+        let live = IndexHandle::open(data_dir, live_dir, schema.clone())?;
+
+        Ok(Self {
+            data_dir: data_dir.into(),
+            schema,
+            fields,
+            live: RwLock::new(Arc::new(live)),
+            staging: Mutex::new(None),
+            meta: Arc::new(meta),
+            embedder,
+            annotations_lock: AsyncMutex::new(()),
+        })
+    }
+
+    fn live(&self) -> Arc<IndexHandle> {
+        self.live.read().unwrap().clone()
+    }
+
+    /// Writes the given repositories to the given index on the blocking
+    /// thread pool.
+    async fn insert_into(&self, handle: Arc<IndexHandle>, repos: Vec<Repository>) -> Result<()> {
+        let fields = self.fields.clone();
+        blocking(move || handle.insert(&fields, &repos)).await
+    }
+
+    /// Stores the given value in the meta file on the blocking thread pool.
+    async fn set_meta(&self, key: &'static str, value: &impl Serialize) -> Result<()> {
+        let (meta, value) = (self.meta.clone(), serde_json::to_value(value)?);
+        blocking(move || meta.set(key, &value)).await
+    }
+
+    /// Reads the repositories with the given IDs from the given index on the
+    /// blocking thread pool.
+    async fn get_from(&self, handle: Arc<IndexHandle>, ids: &[u32]) -> Result<Vec<Repository>> {
+        let (fields, ids) = (self.fields.clone(), ids.to_vec());
+        blocking(move || {
+            let searcher = handle.reader.searcher();
+            let query = TermSetQuery::new(
+                ids.iter()
+                    .map(|id| Term::from_field_u64(fields.id, (*id).into())),
+            );
+
+            let addrs: Vec<_> = searcher
+                .search(&query, &DocSetCollector)?
+                .into_iter()
+                .collect();
+            fields.fetch_docs(&searcher, &addrs)
+        })
+        .await
+    }
+
+    /// Replaces the annotations of the repository with the given ID in the
+    /// given index. Returns `None` if the repository is not in the index.
+    async fn annotate_in(
+        &self,
+        handle: Arc<IndexHandle>,
+        id: u32,
+        annotations: Option<Annotations>,
+    ) -> Result<Option<Repository>> {
+        let Some(mut repo) = self.get_from(handle.clone(), &[id]).await?.pop() else {
+            return Ok(None);
+        };
+
+        repo.annotations = annotations;
+        let (fields, embedder) = (self.fields.clone(), self.embedder.clone());
+        blocking(move || {
+            // Embeddings are not part of the stored source of a document.
+            if let Some(embedder) = &embedder {
+                repo.embedding = embedder.embed_repository(&repo)?;
+            }
+
+            handle.insert(&fields, std::slice::from_ref(&repo))?;
+            Ok(Some(repo))
+        })
+        .await
+    }
+
+    fn with_filters(&self, query: Box<dyn Query>, filter: &Filter) -> Box<dyn Query> {
+        let f = &self.fields;
+        let key_query = |field: Field, value: &str| -> Box<dyn Query> {
+            let term = Term::from_field_text(field, &value.to_lowercase());
+            Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+        };
+        let bool_query = |field: Field, value: bool| -> Box<dyn Query> {
+            let term = Term::from_field_bool(field, value);
+            Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+        };
+
+        let mut clauses = vec![];
+
+        if let Some(language) = &filter.language {
+            clauses.push(key_query(f.language_key, language));
+        }
+        for topic in &filter.topics_all {
+            clauses.push(key_query(f.topic_keys, topic));
+        }
+        if !filter.topics_any.is_empty() {
+            clauses.push(Box::new(TermSetQuery::new(
+                filter
+                    .topics_any
+                    .iter()
+                    .map(|t| Term::from_field_text(f.topic_keys, &t.to_lowercase())),
+            )));
+        }
+        if let Some(license) = &filter.license {
+            clauses.push(key_query(f.license_key, license));
+        }
+        if let Some(owner) = &filter.owner {
+            clauses.push(key_query(f.owner_key, owner));
+        }
+        for list in &filter.lists {
+            clauses.push(key_query(f.list_keys, list));
+        }
+        if let Some(fork) = filter.fork {
+            clauses.push(bool_query(f.fork, fork));
+        }
+        if let Some(archived) = filter.archived {
+            clauses.push(bool_query(f.archived, archived));
+        }
+
+        let ranges = [
+            (f.created_at, &filter.created),
+            (f.updated_at, &filter.updated),
+            (f.starred_at, &filter.starred),
+        ];
+        for (field, range) in ranges {
+            if range.is_empty() {
+                continue;
+            }
+            let bound = |date: Option<chrono::DateTime<chrono::Utc>>| match date {
+                Some(date) => {
+                    Bound::Included(Term::from_field_date_for_search(field, to_date(&date)))
+                }
+                None => Bound::Unbounded,
+            };
+            clauses.push(Box::new(RangeQuery::new(
+                bound(range.after),
+                bound(range.before),
+            )));
+        }
+
+        if clauses.is_empty() {
+            return query;
+        }
+
+        clauses.push(query);
+        Box::new(BooleanQuery::new(
+            clauses.into_iter().map(|q| (Occur::Must, q)).collect(),
+        ))
+    }
+
+    /// Collects the top documents matching the query ordered by the given
+    /// date fast field together with the total number of matches.
+    fn top_docs_by_date(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        offset: usize,
+        field: &str,
+        order: Order,
+    ) -> Result<(Vec<DocAddress>, usize)> {
+        let top_docs = TopDocs::with_limit(limit.max(1))
+            .and_offset(offset)
+            .order_by_fast_field::<DateTime>(field, order);
+        let (docs, total) = searcher.search(query, &(top_docs, Count))?;
+        let addrs = docs.into_iter().map(|(_, addr)| addr).collect();
+        Ok((addrs, total))
+    }
+
+    /// Builds a query matching repositories which share topics, language or
+    /// terms of the name, description and README with the given one.
+    fn similarity_query(&self, searcher: &Searcher, repo: &Repository) -> Box<dyn Query> {
+        let f = &self.fields;
+        let boosted = |field: Field, value: &str, boost: f32| -> (Occur, Box<dyn Query>) {
+            let term = Term::from_field_text(field, &value.to_lowercase());
+            let query = TermQuery::new(term, IndexRecordOption::Basic);
+            (
+                Occur::Should,
+                Box::new(BoostQuery::new(Box::new(query), boost)),
+            )
+        };
+
+        let mut clauses: Vec<_> = repo
+            .topics
+            .iter()
+            .flatten()
+            .map(|topic| boosted(f.topic_keys, topic, SIMILAR_TOPIC_BOOST))
+            .collect();
+        if let Some(language) = &repo.language {
+            clauses.push(boosted(f.language_key, language, SIMILAR_LANGUAGE_BOOST));
+        }
+
+        let text = |field: Field, value: Option<&String>| {
+            (
+                field,
+                value
+                    .map(|v| OwnedValue::Str(v.clone()))
+                    .into_iter()
+                    .collect(),
+            )
+        };
+        // Document frequencies include deleted documents, while the document
+        // count does not. Terms occurring in more documents than are alive
+        // would fail the IDF computation, and they are hardly distinctive.
+        let more_like_this = MoreLikeThisQuery::builder()
+            .with_min_doc_frequency(2)
+            .with_max_doc_frequency(searcher.num_docs())
+            .with_min_term_frequency(1)
+            .with_min_word_length(3)
+            .with_max_query_terms(SIMILAR_MAX_TERMS)
+            .with_document_fields(vec![
+                text(f.name, Some(&repo.name)),
+                text(f.description, repo.description.as_ref()),
+                text(f.readme_content, repo.readme_content.as_ref()),
+            ]);
+        clauses.push((Occur::Should, Box::new(more_like_this)));
+
+        // Never match the repository itself.
+        let id = Term::from_field_u64(f.id, repo.id.into());
+        clauses.push((
+            Occur::MustNot,
+            Box::new(TermQuery::new(id, IndexRecordOption::Basic)),
+        ));
+
+        Box::new(BooleanQuery::new(clauses))
+    }
+
+    /// Counts the values of the facet fields over all documents matching
+    /// the query.
+    fn facets(&self, searcher: &Searcher, query: &dyn Query) -> Result<Facets> {
+        const FACETS: [(&str, &str); 5] = [
+            ("language", "language_key"),
+            ("topics", "topic_keys"),
+            ("license", "license_key"),
+            ("owner", "owner_key"),
+            ("lists", "list_keys"),
+        ];
+
+        let aggs: Aggregations = serde_json::from_value(
+            FACETS
+                .iter()
+                .map(|(name, field)| {
+                    let terms = serde_json::json!({
+                        "terms": { "field": field, "size": MAX_FACET_VALUES }
+                    });
+                    (name.to_string(), terms)
+                })
+                .collect(),
+        )?;
+        let collector = AggregationCollector::from_aggs(aggs, Default::default());
+        let mut res = searcher.search(query, &collector)?;
+
+        let mut values = |name: &str| {
+            let Some(AggregationResult::BucketResult(BucketResult::Terms { buckets, .. })) =
+                res.0.remove(name)
+            else {
+                return vec![];
+            };
+            top_facet_values(buckets.into_iter().filter_map(|bucket| match bucket.key {
+                Key::Str(value) => Some((value, bucket.doc_count as usize)),
+                _ => None,
+            }))
+        };
+
+        Ok(Facets {
+            language: values("language"),
+            topics: values("topics"),
+            license: values("license"),
+            owner: values("owner"),
+            lists: values("lists"),
+        })
+    }
+
+    /// Sets the passages of the description and README of the given hits
+    /// which match the query.
+    fn add_highlights(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        hits: &mut [Repository],
+    ) -> Result<()> {
+        let description = Highlighter::new(
+            searcher,
+            query,
+            self.fields.description,
+            DESCRIPTION_SNIPPET_CHARS,
+            true,
+        )?;
+        let readme = Highlighter::new(
+            searcher,
+            query,
+            self.fields.readme_content,
+            README_SNIPPET_CHARS,
+            false,
+        )?;
+
+        for repo in hits {
+            let highlights = Highlights {
+                description: repo
+                    .description
+                    .as_deref()
+                    .and_then(|text| description.highlight(text)),
+                readme: repo
+                    .readme_content
+                    .as_deref()
+                    .and_then(|text| readme.highlight(text)),
+            };
+            repo.highlights = (!highlights.is_empty()).then_some(highlights);
+        }
+
+        Ok(())
+    }
+
+    fn response(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        (addrs, total): (Vec<DocAddress>, usize),
+        limit: usize,
+        offset: usize,
+        opts: &SearchOptions,
+    ) -> Result<SearchResponse> {
+        Ok(SearchResponse {
+            hits: self.fields.fetch_docs(searcher, &addrs)?,
+            total,
+            offset,
+            limit,
+            facets: opts
+                .facets
+                .then(|| self.facets(searcher, query))
+                .transpose()?,
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl SearchBackend for TantivyBackend {
+    async fn insert_repos(&self, repos: &[Repository]) -> Result<()> {
+        let repos = with_annotations(repos, &self.get_annotations().await?);
+        self.insert_into(self.live(), repos).await
+    }
+
+    async fn remove(&self, ids: &[u32]) -> Result<()> {
+        let (live, fields, ids) = (self.live(), self.fields.clone(), ids.to_vec());
+        blocking(move || live.delete(&fields, &ids)).await
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+        opts: &SearchOptions,
+    ) -> Result<SearchResponse> {
+        let started = Instant::now();
+        let f = &self.fields;
+        let live = self.live();
+        let searcher = live.reader.searcher();
+
+        let vector = embed_query(self.embedder.as_deref(), opts.mode, query)?;
+
+        let query: Box<dyn Query> = if query.trim().is_empty() {
+            Box::new(AllQuery)
+        } else {
+            let mut parser = QueryParser::for_index(
+                &live.index,
+                vec![
+                    f.name,
+                    f.full_name,
+                    f.description,
+                    f.topics,
+                    f.language,
+                    f.readme_content,
+                    f.note,
+                    f.tags,
+                ],
+            );
+            parser.set_field_boost(f.name, 3.0);
+            parser.set_field_boost(f.full_name, 2.5);
+            parser.set_field_boost(f.description, 2.0);
+            parser.set_field_boost(f.topics, 1.5);
+            parser.set_field_boost(f.readme_content, 0.5);
+            parser.set_field_boost(f.tags, 1.5);
+            parser.parse_query_lenient(query).0
+        };
+        let query = self.with_filters(query, &opts.filter);
+
+        if let Some(vector) = vector {
+            let candidates = self.with_filters(Box::new(AllQuery), &opts.filter);
+            // Scores every candidate, which is too slow for the executor.
+            let docs = {
+                let (searcher, query, candidates) =
+                    (searcher.clone(), query.box_clone(), candidates.box_clone());
+                let opts = opts.clone();
+                blocking(move || {
+                    semantic_top_docs(
+                        &searcher,
+                        &query,
+                        &candidates,
+                        &vector,
+                        &opts,
+                        (limit, offset),
+                    )
+                })
+                .await?
+            };
+
+            let mut res = self.response(&searcher, &candidates, docs, limit, offset, opts)?;
+            self.add_highlights(&searcher, &query, &mut res.hits)?;
+            METRICS.observe_search("tantivy", opts.mode, started.elapsed(), res.total);
+            return Ok(res);
+        }
+
+        let docs = match opts.sort {
+            Some(sort) => self.top_docs_by_date(
+                &searcher,
+                &query,
+                limit,
+                offset,
+                "starred_at",
+                sort_order(sort),
+            )?,
+            None => {
+                let top_docs = TopDocs::with_limit(limit.max(1)).and_offset(offset);
+                let (docs, total) = searcher.search(&query, &(top_docs, Count))?;
+                (docs.into_iter().map(|(_, addr)| addr).collect(), total)
+            }
+        };
+
+        let mut res = self.response(&searcher, &query, docs, limit, offset, opts)?;
+        self.add_highlights(&searcher, &query, &mut res.hits)?;
+        METRICS.observe_search("tantivy", opts.mode, started.elapsed(), res.total);
+
+        Ok(res)
+    }
+
+    async fn list(
+        &self,
+        limit: usize,
+        offset: usize,
+        opts: &SearchOptions,
+    ) -> Result<SearchResponse> {
+        let searcher = self.live().reader.searcher();
+        let query = self.with_filters(Box::new(AllQuery), &opts.filter);
+
+        let (field, order) = match opts.sort {
+            Some(sort) => ("starred_at", sort_order(sort)),
+            None => ("updated_at", Order::Desc),
+        };
+        let docs = self.top_docs_by_date(&searcher, &query, limit, offset, field, order)?;
+
+        self.response(&searcher, &query, docs, limit, offset, opts)
+    }
+
+    async fn list_ids(&self) -> Result<Vec<u32>> {
+        let searcher = self.live().reader.searcher();
+        let id_field = self.fields.id;
+
+        // Reads every stored document.
+        blocking(move || {
+            let mut ids = vec![];
+            for addr in searcher.search(&AllQuery, &DocSetCollector)? {
+                let doc: TantivyDocument = searcher.doc(addr)?;
+                if let Some(id) = doc.get_first(id_field).and_then(|v| v.as_u64()) {
+                    ids.push(id as u32);
+                }
+            }
+            Ok(ids)
+        })
+        .await
+    }
+
+    async fn get_repos(&self, ids: &[u32]) -> Result<Vec<Repository>> {
+        self.get_from(self.live(), ids).await
+    }
+
+    async fn get_annotations(&self) -> Result<HashMap<u32, Annotations>> {
+        Ok(self.meta.get(ANNOTATIONS_KEY)?.unwrap_or_default())
+    }
+
+    async fn set_annotations(
+        &self,
+        id: u32,
+        annotations: Annotations,
+    ) -> Result<Option<Repository>> {
+        let _lock = self.annotations_lock.lock().await;
+
+        let live = self.live();
+        if self.get_from(live.clone(), &[id]).await?.is_empty() {
+            return Ok(None);
+        }
+
+        let annotations = Some(annotations.normalized()).filter(|a| !a.is_empty());
+        let mut stored = self.get_annotations().await?;
+        match &annotations {
+            Some(annotations) => stored.insert(id, annotations.clone()),
+            None => stored.remove(&id),
+        };
+        self.set_meta(ANNOTATIONS_KEY, &stored).await?;
+
+        // A running full scrape might already have written the repository
+        // to the staging index.
+        let staging = self.staging.lock().unwrap().clone();
+        if let Some(staging) = staging {
+            self.annotate_in(staging, id, annotations.clone()).await?;
+        }
+
+        self.annotate_in(live, id, annotations).await
+    }
+
+    async fn retain_annotations(&self, ids: &HashSet<u32>) -> Result<()> {
+        let _lock = self.annotations_lock.lock().await;
+
+        let mut stored = self.get_annotations().await?;
+        stored.retain(|id, _| ids.contains(id));
+        self.set_meta(ANNOTATIONS_KEY, &stored).await
+    }
+
+    async fn similar(&self, id: u32, limit: usize) -> Result<Option<Vec<Repository>>> {
+        let Some(repo) = self.get_repos(&[id]).await?.pop() else {
+            return Ok(None);
+        };
+
+        let searcher = self.live().reader.searcher();
+        let query = self.similarity_query(&searcher, &repo);
+        let id = Term::from_field_u64(self.fields.id, id.into());
+        let embedder = self.embedder.clone();
+
+        // Embeds the README and, with an embedder, scores every repository.
+        let addrs = blocking({
+            let searcher = searcher.clone();
+            move || {
+                let embedding = match &embedder {
+                    Some(embedder) => embedder.embed_repository(&repo)?,
+                    None => None,
+                };
+
+                let Some(embedding) = embedding else {
+                    let addrs = searcher
+                        .search(&query, &TopDocs::with_limit(limit.max(1)))?
+                        .into_iter()
+                        .map(|(_, addr)| addr)
+                        .collect();
+                    return Ok(addrs);
+                };
+
+                let candidates = BooleanQuery::new(vec![
+                    (Occur::Must, Box::new(AllQuery) as Box<dyn Query>),
+                    (
+                        Occur::MustNot,
+                        Box::new(TermQuery::new(id, IndexRecordOption::Basic)),
+                    ),
+                ]);
+                let opts = SearchOptions {
+                    mode: SearchMode::Hybrid,
+                    ..Default::default()
+                };
+                let (addrs, _) = semantic_top_docs(
+                    &searcher,
+                    &query,
+                    &candidates,
+                    &embedding,
+                    &opts,
+                    (limit, 0),
+                )?;
+                Ok(addrs)
+            }
+        })
+        .await?;
+
+        Ok(Some(self.fields.fetch_docs(&searcher, &addrs)?))
+    }
+
+    async fn create_staging(&self) -> Result<()> {
+        // Release the writer of a previous staging index before its
+        // directory is removed. Scrapes are serialized, so no other staging
+        // index is created in the meantime.
+        self.staging.lock().unwrap().take();
+
+        let live_dir = self.live().dir;
+        let dir = INDEX_DIRS
+            .into_iter()
+            .find(|dir| *dir != live_dir)
+            .expect("there are two index directories");
+
+        let (data_dir, schema) = (self.data_dir.clone(), self.schema.clone());
+        let staging = blocking(move || {
+            let path = data_dir.join(dir);
+            if path.exists() {
+                fs::remove_dir_all(&path)?;
+            }
+            IndexHandle::open(&data_dir, dir, schema)
+        })
+        .await?;
+        *self.staging.lock().unwrap() = Some(Arc::new(staging));
+
+        Ok(())
+    }
+
+    async fn insert_staging(&self, repos: &[Repository]) -> Result<()> {
+        let staging = self.staging.lock().unwrap().clone();
+        let staging = staging.ok_or(DatabaseError::MissingStaging)?;
+        let repos = with_annotations(repos, &self.get_annotations().await?);
+        self.insert_into(staging, repos).await
+    }
+
+    async fn swap_staging(&self) -> Result<()> {
+        let staging = self.staging.lock().unwrap().take();
+        let staging = staging.ok_or(DatabaseError::MissingStaging)?;
+
+        // The meta file is replaced atomically, so a crash at any point
+        // either keeps the previous or opens the new index on restart.
+        self.set_meta(LIVE_INDEX_KEY, &staging.dir).await?;
+        *self.live.write().unwrap() = staging;
+
+        Ok(())
+    }
+
+    async fn get_index_dates(&self) -> Result<IndexDates> {
+        Ok(self.meta.get(INDEX_DATES_KEY)?.unwrap_or_default())
+    }
+
+    async fn set_index_dates(&self, dates: IndexDates) -> Result<()> {
+        self.set_meta(INDEX_DATES_KEY, &dates).await
+    }
+
+    async fn get_request_cache(&self) -> Result<RequestCache> {
+        Ok(self.meta.get(REQUEST_CACHE_KEY)?.unwrap_or_default())
+    }
+
+    async fn set_request_cache(&self, cache: &RequestCache) -> Result<()> {
+        self.set_meta(REQUEST_CACHE_KEY, cache).await
+    }
+
+    async fn get_info(&self) -> Result<ServerInfo> {
+        let index_count = self.live().reader.searcher().num_docs() as usize;
+        let index_dates = self.get_index_dates().await?;
+
+        Ok(ServerInfo {
+            index_dates,
+            index_count,
+            semantic_search: self.semantic_search(),
+            users: vec![],
+        })
+    }
+
+    async fn check(&self) -> Result<()> {
+        let live = self.live();
+        // Reads the metadata from disk, which fails if the index has been
+        // removed or damaged since it has been opened.
+        let index = live.index.clone();
+        let metas = blocking(move || Ok(index.load_metas()?)).await?;
+        if metas.schema != self.schema {
+            return Err(DatabaseError::MisconfiguredIndex(live.dir.to_string()));
+        }
+        self.get_index_dates().await?;
+
+        Ok(())
+    }
+
+    fn semantic_search(&self) -> bool {
+        self.embedder.is_some()
+    }
+}
+
+/// Ranks all documents matching the `candidates` query by blending the
+/// similarity of their embedding to the query vector with their
+/// normalized score of the `keyword` query, weighted by the semantic
+/// ratio of the search mode. Documents without embedding only match by
+/// keyword.
+fn semantic_top_docs(
+    searcher: &Searcher,
+    keyword: &dyn Query,
+    candidates: &dyn Query,
+    vector: &[f32],
+    opts: &SearchOptions,
+    (limit, offset): (usize, usize),
+) -> Result<(Vec<DocAddress>, usize)> {
+    let ratio = semantic_ratio(opts.mode);
+
+    let mut keyword_scores = HashMap::new();
+    if ratio < 1.0 {
+        let top_docs = TopDocs::with_limit((searcher.num_docs() as usize).max(1));
+        let docs = searcher.search(keyword, &top_docs)?;
+        let max_score = docs.first().map_or(0.0, |(score, _)| *score);
+        if max_score > 0.0 {
+            keyword_scores.extend(
+                docs.into_iter()
+                    .map(|(score, addr)| (addr, score / max_score)),
+            );
+        }
+    }
+
+    let segments = searcher.segment_readers();
+    let embeddings = segments
+        .iter()
+        .map(|segment| segment.fast_fields().bytes("embedding"))
+        .collect::<tantivy::Result<Vec<_>>>()?;
+
+    let mut buf = vec![];
+    let mut hits = vec![];
+    for addr in searcher.search(candidates, &DocSetCollector)? {
+        let similarity = embeddings[addr.segment_ord as usize]
+            .as_ref()
+            .and_then(|column| read_embedding(column, addr.doc_id, &mut buf))
+            .map(|embedding| embeddings::similarity(vector, &embedding));
+        let keyword_score = keyword_scores.get(&addr).copied();
+        if similarity.is_none() && keyword_score.is_none() {
+            continue;
+        }
+
+        let score = ratio * similarity.unwrap_or_default()
+            + (1.0 - ratio) * keyword_score.unwrap_or_default();
+        hits.push((score, addr));
+    }
+
+    match opts.sort {
+        Some(sort) => {
+            let dates = segments
+                .iter()
+                .map(|segment| segment.fast_fields().date("starred_at"))
+                .collect::<tantivy::Result<Vec<_>>>()?;
+            hits.sort_by_key(|(_, addr)| dates[addr.segment_ord as usize].first(addr.doc_id));
+            if sort_order(sort) == Order::Desc {
+                hits.reverse();
+            }
+        }
+        None => hits.sort_by(|a, b| b.0.total_cmp(&a.0)),
+    }
+
+    let total = hits.len();
+    let addrs = hits
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(_, addr)| addr)
+        .collect();
+
+    Ok((addrs, total))
+}
+
+fn read_embedding(column: &BytesColumn, doc: DocId, buf: &mut Vec<u8>) -> Option<Vec<f32>> {
+    let ord = column.term_ords(doc).next()?;
+    buf.clear();
+    column.ord_to_bytes(ord, buf).ok()?;
+
+    let embedding = buf
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    Some(embedding)
+}
+
+/// Runs the given index operation on the blocking thread pool, so that
+/// commits and scans of the whole index do not stall the async workers.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    task::spawn_blocking(f).await?
+}
+
+fn to_date(date: &chrono::DateTime<chrono::Utc>) -> DateTime {
+    DateTime::from_timestamp_secs(date.timestamp())
+}
+
+/// Highlights the passages of a field which match a query.
+struct Highlighter {
+    snippets: SnippetGenerator,
+    tokenizer: TextAnalyzer,
+    max_num_chars: usize,
+    whole: bool,
+}
+
+impl Highlighter {
+    /// With `whole` set, the whole text is returned. Otherwise, only the
+    /// fragment of the snippet is returned, marked with ellipses where it has
+    /// been cropped.
+    fn new(
+        searcher: &Searcher,
+        query: &dyn Query,
+        field: Field,
+        max_num_chars: usize,
+        whole: bool,
+    ) -> Result<Self> {
+        let mut snippets = SnippetGenerator::create(searcher, query, field)?;
+        snippets.set_max_num_chars(max_num_chars);
+        let tokenizer = searcher.index().tokenizer_for_field(field)?;
+
+        Ok(Self {
+            snippets,
+            tokenizer,
+            max_num_chars,
+            whole,
+        })
+    }
+
+    fn highlight(&self, text: &str) -> Option<HighlightedText> {
+        let snippet = self.snippets.snippet(text);
+        if snippet.highlighted().is_empty() {
+            return None;
+        }
+
+        let fragment = snippet.fragment();
+        let offset = self.fragment_offset(text, fragment)?;
+        // Fragments end at word boundaries, so cropped punctuation is added back.
+        let cropped = |rest: &str| !self.whole && rest.contains(char::is_alphanumeric);
+        let start = if cropped(&text[..offset]) { offset } else { 0 };
+        let end = offset + fragment.len();
+        let end = if cropped(&text[end..]) { end } else { text.len() };
+
+        let mut parts = vec![];
+        if start > 0 {
+            parts.push(("…", false));
+        }
+        let mut pos = start;
+        for range in snippet.highlighted() {
+            let (from, to) = (offset + range.start, offset + range.end);
+            parts.push((&text[pos..from], false));
+            parts.push((&text[from..to], true));
+            pos = to;
+        }
+        parts.push((&text[pos..end], false));
+        if end < text.len() {
+            parts.push(("…", false));
+        }
+
+        highlighted_text(parts)
+    }
+
+    /// Returns the position of the snippet fragment in the text. The snippet
+    /// only carries offsets relative to its fragment, so the fragment
+    /// boundaries of the snippet generator are replayed. Of equally scored
+    /// fragments the generator picks the first one.
+    fn fragment_offset(&self, text: &str, fragment: &str) -> Option<usize> {
+        let mut tokenizer = self.tokenizer.clone();
+        let mut stream = tokenizer.token_stream(text);
+        let mut start = 0;
+        let mut starts = vec![start];
+        while let Some(token) = stream.next() {
+            if token.offset_to - start > self.max_num_chars {
+                start = token.offset_from;
+                starts.push(start);
+            }
+        }
+
+        starts
+            .into_iter()
+            .find(|&start| text[start..].starts_with(fragment))
+    }
+}
+
+fn sort_order(sort: Sort) -> Order {
+    match sort {
+        Sort::StarredAtDesc => Order::Desc,
+        Sort::StarredAtAsc => Order::Asc,
+    }
+}
+
+/// Opens the index at the given path or creates it if it does not exist.
+///
+/// When the schema of an existing index does not match the current one, the
+/// index is dropped and re-created. Its contents are restored on the next
+/// scrape.
+fn open_index(path: &Path, schema: Schema) -> Result<Index> {
+    fs::create_dir_all(path)?;
+    let dir = MmapDirectory::open(path).map_err(TantivyError::from)?;
+
+    match Index::open_or_create(dir, schema.clone()) {
+        Ok(index) => Ok(index),
+        Err(TantivyError::SchemaError(_)) => {
+            warn!(
+                "Schema of index at {} has changed; re-creating index",
+                path.display()
+            );
+            fs::remove_dir_all(path)?;
+            fs::create_dir_all(path)?;
+            Ok(Index::create_in_dir(path, schema)?)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Simple JSON file backed key-value store used as the equivalent of the
+/// Meilisearch `meta` index.
+struct MetaStore {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, serde_json::Value>>,
+}
+
+impl MetaStore {
+    fn open(path: PathBuf) -> Result<Self> {
+        let entries = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let entries = self.entries.lock().unwrap();
+        let value = entries
+            .get(key)
+            .map(|v| serde_json::from_value(v.clone()))
+            .transpose()?;
+        Ok(value)
+    }
+
+    fn set<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key.into(), serde_json::to_value(value)?);
+
+        files::write_atomic(&self.path, &serde_json::to_vec(&*entries)?, false)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use starsearch_sdk::models::DateRange;
+    use tempfile::TempDir;
+
+    /// Returns the stars `rust-lang/rust`, a fork of it and
+    /// `zekroTJA/starsearch`, starred on consecutive days.
+    fn repositories() -> Vec<Repository> {
+        let mut rust = testing::repository(1, "rust-lang/rust", 0);
+        rust.description = Some("Empowering everyone to build reliable software".into());
+        rust.language = Some("Rust".into());
+        rust.topics = Some(vec!["compiler".into(), "language".into()]);
+
+        let mut fork = testing::repository(2, "zekroTJA/rust", 1);
+        fork.description = rust.description.clone();
+        fork.language = Some("Rust".into());
+        fork.fork = true;
+
+        let mut starsearch = testing::repository(3, "zekroTJA/starsearch", 2);
+        starsearch.description = Some("Search through your GitHub stars".into());
+        starsearch.language = Some("Go".into());
+        starsearch.readme_content = Some("Finds starred repositories quickly.".into());
+
+        vec![rust, fork, starsearch]
+    }
+
+    async fn backend(dir: &TempDir) -> TantivyBackend {
+        let db = TantivyBackend::open(dir.path(), None).unwrap();
+        db.insert_repos(&repositories()).await.unwrap();
+        db
+    }
+
+    async fn search(db: &TantivyBackend, query: &str, opts: SearchOptions) -> Vec<u32> {
+        let res = db.search(query, 10, 0, &opts).await.unwrap();
+        assert_eq!(res.total, res.hits.len());
+        res.hits.iter().map(|repo| repo.id).collect()
+    }
+
+    fn filtered(filter: Filter) -> SearchOptions {
+        SearchOptions {
+            filter,
+            ..Default::default()
+        }
+    }
+
+    #[rocket::async_test]
+    async fn search_inserted() {
+        let dir = TempDir::new().unwrap();
+        let db = backend(&dir).await;
+
+        assert_eq!(search(&db, "stars", Default::default()).await, [3]);
+        assert_eq!(search(&db, "quickly", Default::default()).await, [3]);
+        assert!(search(&db, "nothing", Default::default()).await.is_empty());
+
+        let mut ids = search(&db, "reliable", Default::default()).await;
+        ids.sort();
+        assert_eq!(ids, [1, 2]);
+
+        let res = db
+            .search("stars", 10, 0, &Default::default())
+            .await
+            .unwrap();
+        let highlights = res.hits[0].highlights.as_ref().unwrap();
+        let description = highlights.description.as_ref().unwrap();
+        assert_eq!(description.parts()[1], ("stars", true));
+
+        // Inserting a repository again replaces it.
+        let mut repo = repositories().remove(2);
+        repo.description = Some("Search through starred repositories".into());
+        db.insert_repos(&[repo]).await.unwrap();
+        assert!(search(&db, "GitHub", Default::default()).await.is_empty());
+        assert_eq!(db.get_info().await.unwrap().index_count, 3);
+    }
+
+    #[rocket::async_test]
+    async fn search_filtered() {
+        let dir = TempDir::new().unwrap();
+        let db = backend(&dir).await;
+
+        let filter = Filter {
+            language: Some("rust".into()),
+            fork: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(search(&db, "", filtered(filter)).await, [1]);
+
+        let filter = Filter {
+            owner: Some("zekrotja".into()),
+            ..Default::default()
+        };
+        let mut ids = search(&db, "", filtered(filter)).await;
+        ids.sort();
+        assert_eq!(ids, [2, 3]);
+
+        let filter = Filter {
+            topics_all: vec!["compiler".into(), "language".into()],
+            ..Default::default()
+        };
+        assert_eq!(search(&db, "", filtered(filter)).await, [1]);
+
+        let starred = |day| testing::repository(0, "a/b", day).starred_at;
+        let filter = Filter {
+            starred: DateRange {
+                after: starred(1),
+                before: starred(1),
+            },
+            ..Default::default()
+        };
+        assert_eq!(search(&db, "reliable", filtered(filter)).await, [2]);
+    }
+
+    #[rocket::async_test]
+    async fn sort_by_starred_date() {
+        let dir = TempDir::new().unwrap();
+        let db = backend(&dir).await;
+
+        for (sort, expected) in [
+            (Sort::StarredAtAsc, [1, 2, 3]),
+            (Sort::StarredAtDesc, [3, 2, 1]),
+        ] {
+            let opts = SearchOptions {
+                sort: Some(sort),
+                ..Default::default()
+            };
+            assert_eq!(search(&db, "", opts.clone()).await, expected);
+
+            let res = db.list(2, 1, &opts).await.unwrap();
+            let ids: Vec<_> = res.hits.iter().map(|repo| repo.id).collect();
+            assert_eq!(ids, expected[1..]);
+            assert_eq!(res.total, 3);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn swap_staging_index() {
+        let dir = TempDir::new().unwrap();
+        let db = backend(&dir).await;
+
+        db.create_staging().await.unwrap();
+        db.insert_staging(&repositories()[2..]).await.unwrap();
+        let mut ids = db.list_ids().await.unwrap();
+        ids.sort();
+        assert_eq!(ids, [1, 2, 3]);
+
+        db.swap_staging().await.unwrap();
+        assert_eq!(db.list_ids().await.unwrap(), [3]);
+        assert!(matches!(
+            db.swap_staging().await,
+            Err(DatabaseError::MissingStaging)
+        ));
+
+        // The swapped index stays live after reopening.
+        drop(db);
+        let db = TantivyBackend::open(dir.path(), None).unwrap();
+        assert_eq!(db.list_ids().await.unwrap(), [3]);
+
+        // The previous index is replaced by the next staging index.
+        db.create_staging().await.unwrap();
+        db.insert_staging(&repositories()).await.unwrap();
+        db.swap_staging().await.unwrap();
+        assert_eq!(db.list_ids().await.unwrap().len(), 3);
+    }
+
+    #[rocket::async_test]
+    async fn annotations_survive_scrapes() {
+        let dir = TempDir::new().unwrap();
+        let db = backend(&dir).await;
+
+        let annotations = Annotations {
+            note: Some(" Great for bookmarks ".into()),
+            tags: vec!["tools".into(), " tools".into()],
+        };
+        let repo = db.set_annotations(3, annotations).await.unwrap().unwrap();
+        let expected = Annotations {
+            note: Some("Great for bookmarks".into()),
+            tags: vec!["tools".into()],
+        };
+        assert_eq!(repo.annotations.as_ref(), Some(&expected));
+        assert!(db
+            .set_annotations(4, expected.clone())
+            .await
+            .unwrap()
+            .is_none());
+
+        assert_eq!(search(&db, "bookmarks", Default::default()).await, [3]);
+        assert_eq!(search(&db, "tools", Default::default()).await, [3]);
+
+        // Annotations set during a full scrape are kept in the staging index
+        // and scraped repositories get the stored annotations attached.
+        db.create_staging().await.unwrap();
+        db.insert_staging(&repositories()[..1]).await.unwrap();
+        db.set_annotations(1, expected.clone()).await.unwrap();
+        db.insert_staging(&repositories()[2..]).await.unwrap();
+        db.swap_staging().await.unwrap();
+
+        let mut ids = search(&db, "bookmarks", Default::default()).await;
+        ids.sort();
+        assert_eq!(ids, [1, 3]);
+
+        db.retain_annotations(&HashSet::from([1])).await.unwrap();
+        let stored = db.get_annotations().await.unwrap();
+        assert_eq!(stored, HashMap::from([(1, expected)]));
+    }
+
+    fn highlighter(text: &str, query: &str, max_num_chars: usize, whole: bool) -> Highlighter {
+        let mut schema = Schema::builder();
+        let field = schema.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema.build());
+
+        let mut writer: IndexWriter = index.writer(WRITER_MEMORY_BUDGET).unwrap();
+        writer.add_document(tantivy::doc!(field => text)).unwrap();
+        writer.commit().unwrap();
+
+        let searcher = index.reader().unwrap().searcher();
+        let query = QueryParser::for_index(&index, vec![field])
+            .parse_query(query)
+            .unwrap();
+        Highlighter::new(&searcher, &*query, field, max_num_chars, whole).unwrap()
+    }
+
+    fn matches(text: &HighlightedText) -> Vec<&str> {
+        text.parts()
+            .into_iter()
+            .filter(|(_, matched)| *matched)
+            .map(|(part, _)| part)
+            .collect()
+    }
+
+    #[test]
+    fn highlight_whole_text() {
+        let text = "A fast search engine. Search it!";
+        let res = highlighter(text, "search", 150, true)
+            .highlight(text)
+            .unwrap();
+        assert_eq!(res.text, text);
+        assert_eq!(matches(&res), ["search", "Search"]);
+    }
+
+    #[test]
+    fn highlight_no_match() {
+        let text = "A fast search engine";
+        assert!(highlighter(text, "slow", 150, true)
+            .highlight(text)
+            .is_none());
+    }
+
+    #[test]
+    fn highlight_cropped_fragment() {
+        let text = "one two three rust four five six seven eight nine.";
+        let res = highlighter(text, "rust", 15, false)
+            .highlight(text)
+            .unwrap();
+        assert_eq!(res.text, "…rust four five…");
+        assert_eq!(matches(&res), ["rust"]);
+    }
+
+    #[test]
+    fn highlight_repeated_passage() {
+        // The text of the selected fragment first occurs across the boundary
+        // of the first two fragments.
+        let text = "ab rust rust cd rust rust";
+        let res = highlighter(text, "rust", 9, false).highlight(text).unwrap();
+        assert_eq!(res.text, "…rust rust");
+        assert_eq!(res.matches.len(), 2);
+        assert_eq!(res.matches[0].start, "…".len());
+    }
+}
@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+pub type Result<T, E = EmbeddingError> = core::result::Result<T, E>;
+
+#[derive(Error, Debug)]
+pub enum EmbeddingError {
+    #[error("failed reading model: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("tokenizer error: {0}")]
+    TokenizerError(#[from] tokenizers::Error),
+
+    #[error("invalid model weights: {0}")]
+    InvalidWeights(#[from] safetensors::SafeTensorError),
+
+    #[error("unsupported model: {0}")]
+    UnsupportedModel(String),
+}
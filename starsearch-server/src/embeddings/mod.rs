@@ -0,0 +1,148 @@
+pub mod errors;
+
+use errors::{EmbeddingError, Result};
+use half::{bf16, f16};
+use safetensors::{Dtype, SafeTensors};
+use starsearch_sdk::models::Repository;
+use std::fs;
+use std::path::Path;
+use tokenizers::Tokenizer;
+
+/// Name of the tensor holding the token embeddings.
+const EMBEDDINGS_TENSOR: &str = "embeddings";
+
+/// Maximum number of tokens of a text which are embedded.
+const MAX_TOKENS: usize = 512;
+
+/// Maximum number of characters of a README which are embedded. Longer
+/// READMEs are cut off before tokenizing, as only the first tokens are
+/// embedded anyway.
+const MAX_README_CHARS: usize = 4096;
+
+/// Computes text embeddings locally on the CPU with a static embedding model
+/// in the [Model2Vec](https://github.com/MinishLab/model2vec) format.
+///
+/// The model directory must contain the `tokenizer.json` and the
+/// `model.safetensors` holding the token embeddings. A text is embedded as
+/// the normalized mean of the embeddings of its tokens.
+pub struct Embedder {
+    tokenizer: Tokenizer,
+    embeddings: Vec<f32>,
+    dimensions: usize,
+    /// Tokens which carry no meaning and are not embedded.
+    skipped: Vec<u32>,
+}
+
+impl Embedder {
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+
+        let mut tokenizer = Tokenizer::from_file(dir.join("tokenizer.json"))?;
+        tokenizer.with_padding(None);
+        tokenizer.with_truncation(None)?;
+
+        let weights = fs::read(dir.join("model.safetensors"))?;
+        let weights = SafeTensors::deserialize(&weights)?;
+        let tensor = weights.tensor(EMBEDDINGS_TENSOR)?;
+
+        let &[vocab_size, dimensions] = tensor.shape() else {
+            return Err(EmbeddingError::UnsupportedModel(format!(
+                "expected a 2-dimensional embeddings tensor, got shape {:?}",
+                tensor.shape()
+            )));
+        };
+
+        let data = tensor.data();
+        let embeddings: Vec<f32> = match tensor.dtype() {
+            Dtype::F32 => data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            Dtype::F16 => data
+                .chunks_exact(2)
+                .map(|b| f16::from_le_bytes([b[0], b[1]]).to_f32())
+                .collect(),
+            Dtype::BF16 => data
+                .chunks_exact(2)
+                .map(|b| bf16::from_le_bytes([b[0], b[1]]).to_f32())
+                .collect(),
+            dtype => {
+                return Err(EmbeddingError::UnsupportedModel(format!(
+                    "unsupported embeddings type {dtype}"
+                )))
+            }
+        };
+        if embeddings.len() != vocab_size * dimensions {
+            return Err(EmbeddingError::UnsupportedModel(
+                "embeddings tensor is truncated".into(),
+            ));
+        }
+
+        let skipped = ["[PAD]", "[UNK]", "<pad>", "<unk>"]
+            .into_iter()
+            .filter_map(|token| tokenizer.token_to_id(token))
+            .collect();
+
+        Ok(Self {
+            tokenizer,
+            embeddings,
+            dimensions,
+            skipped,
+        })
+    }
+
+    /// The number of dimensions of the computed vectors.
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Embeds the given text into a unit vector. Returns `None` if the text
+    /// contains no known tokens.
+    pub fn embed(&self, text: &str) -> Result<Option<Vec<f32>>> {
+        let encoding = self.tokenizer.encode(text, false)?;
+
+        let mut vector = vec![0.0; self.dimensions];
+        let mut count = 0;
+        for &id in encoding.get_ids().iter().take(MAX_TOKENS) {
+            if self.skipped.contains(&id) {
+                continue;
+            }
+            let start = id as usize * self.dimensions;
+            let Some(embedding) = self.embeddings.get(start..start + self.dimensions) else {
+                continue;
+            };
+
+            vector.iter_mut().zip(embedding).for_each(|(v, e)| *v += e);
+            count += 1;
+        }
+
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if count == 0 || norm == 0.0 {
+            return Ok(None);
+        }
+        vector.iter_mut().for_each(|v| *v /= norm);
+
+        Ok(Some(vector))
+    }
+
+    /// Embeds the description, topics and README of the given repository.
+    pub fn embed_repository(&self, repo: &Repository) -> Result<Option<Vec<f32>>> {
+        let mut text = vec![repo.name.as_str()];
+        text.extend(repo.description.as_deref());
+        text.extend(repo.topics.iter().flatten().map(String::as_str));
+        if let Some(readme) = &repo.readme_content {
+            let end = readme
+                .char_indices()
+                .nth(MAX_README_CHARS)
+                .map_or(readme.len(), |(idx, _)| idx);
+            text.push(&readme[..end]);
+        }
+
+        self.embed(&text.join("\n"))
+    }
+}
+
+/// Cosine similarity of two unit vectors.
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
@@ -1,23 +1,30 @@
 mod config;
 mod db;
+mod embeddings;
+mod files;
+mod metrics;
+mod oauth;
 mod scraper;
+#[cfg(test)]
+mod testing;
+mod tokens;
+mod users;
 mod web;
 
 use config::Config;
-use db::Database;
+use embeddings::Embedder;
 use env_logger::Env;
-use scraper::Scraper;
-use std::error::Error;
+use oauth::OAuth;
+use starsearch_sdk::models::JobKind;
+use std::process;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
+use tokens::ApiTokens;
+use tokio_cron_scheduler::JobScheduler;
+use users::Users;
 
 #[macro_use]
 extern crate rocket;
 
-const DEFAULT_SCRAPE_FAST_INTERVAL: u64 = 3500;
-const DEFAULT_SCRAPE_FULL_INTERVAL: u64 = 3600 * 12;
-
 #[allow(clippy::result_large_err)]
 #[rocket::main]
 async fn main() -> Result<(), rocket::Error> {
@@ -29,88 +36,49 @@ async fn main() -> Result<(), rocket::Error> {
 
     let cfg = Config::parse().expect("failed parsing config");
 
-    let db = Database::new(&cfg.meilisearch_url, cfg.meilisearch_apikey.as_ref())
-        .await
-        .expect("failed creating database connection");
-    let db = Arc::new(db);
+    let embedder = cfg.embedding_model.as_ref().map(|dir| {
+        info!("Loading embedding model from {dir} ...");
+        Arc::new(Embedder::load(dir).expect("failed loading embedding model"))
+    });
 
-    let scraper = Scraper::new(cfg.github_username, cfg.github_apitoken, db.clone())
-        .expect("failed constructing scraper");
-    let scraper = Arc::new(scraper);
+    let usernames = cfg.github_usernames();
+    let oauth = OAuth::from_config(&cfg).expect("failed constructing OAuth client");
+    assert!(
+        !usernames.is_empty() || oauth.is_some(),
+        "neither a GitHub username nor a GitHub OAuth app is configured"
+    );
 
-    let fast_interval = cfg
-        .scrape_fast_interval_seconds
-        .unwrap_or(DEFAULT_SCRAPE_FAST_INTERVAL);
-    let full_interval = cfg
-        .scrape_full_interval_seconds
-        .unwrap_or(DEFAULT_SCRAPE_FULL_INTERVAL);
+    let tokens = ApiTokens::from_config(&cfg).expect("failed parsing API tokens");
 
     let sched = JobScheduler::new()
         .await
         .expect("failed creating scheduler");
 
-    schedule_fast_scraping(&sched, scraper.clone(), fast_interval)
-        .await
-        .expect("failed scheduling fast scraping job");
-
-    schedule_full_scraping(&sched, scraper.clone(), full_interval)
-        .await
-        .expect("failed scheduling fast scraping job");
+    let skip_initial_scrape = cfg.skip_initial_scrape.is_some_and(|v| v);
+    let users = Users::new(cfg, embedder, sched.clone()).expect("failed opening accounts");
+    // Setup fails if the search backend does not become reachable within
+    // the connect timeout, which is reported as error instead of a panic.
+    for username in usernames {
+        if let Err(err) = users.add(&username).await {
+            error!("Failed setting up user {username}: {err}");
+            process::exit(1);
+        }
+    }
+    if oauth.is_some() {
+        if let Err(err) = users.restore_accounts().await {
+            error!("Failed restoring accounts: {err}");
+            process::exit(1);
+        }
+    }
 
     sched.start().await.expect("failed starting scheduler");
 
-    if !cfg.skip_initial_scrape.is_some_and(|v| v) {
-        info!("Starting initial scraping ...");
-        let scraper = scraper.clone();
-        rocket::tokio::spawn(async move {
-            if let Err(err) = scrape(scraper, true).await {
-                error!("Initial scraping failed: {err}");
-            }
-        });
+    if !skip_initial_scrape {
+        for user in users.all() {
+            info!("Starting initial scraping of {} ...", user.name);
+            user.scraper.start(JobKind::Fast);
+        }
     }
 
-    web::run(db, scraper).await
-}
-
-async fn scrape(scraper: Arc<Scraper>, fast: bool) -> Result<(), Box<dyn Error>> {
-    scraper.index(fast).await?;
-    Ok(())
-}
-
-async fn schedule_fast_scraping(
-    sched: &JobScheduler,
-    scraper: Arc<Scraper>,
-    interval_seconds: u64,
-) -> Result<(), JobSchedulerError> {
-    let job = Job::new_repeated_async(Duration::from_secs(interval_seconds), move |_uuid, _l| {
-        let scraper = scraper.clone();
-        Box::pin(async move {
-            info!("Starting scheduled fast scraping ...");
-            if let Err(err) = scrape(scraper, true).await {
-                error!("Fast scraping failed: {err}");
-            }
-        })
-    })?;
-
-    sched.add(job).await?;
-    Ok(())
-}
-
-async fn schedule_full_scraping(
-    sched: &JobScheduler,
-    scraper: Arc<Scraper>,
-    interval_seconds: u64,
-) -> Result<(), JobSchedulerError> {
-    let job = Job::new_repeated_async(Duration::from_secs(interval_seconds), move |_uuid, _l| {
-        let scraper = scraper.clone();
-        Box::pin(async move {
-            info!("Starting scheduled full scraping ...");
-            if let Err(err) = scrape(scraper, false).await {
-                error!("Full scraping failed: {err}");
-            }
-        })
-    })?;
-
-    sched.add(job).await?;
-    Ok(())
+    web::run(users, oauth, tokens).await
 }
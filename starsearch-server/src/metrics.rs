@@ -0,0 +1,406 @@
+use starsearch_sdk::models::{Job, JobKind, JobState, SearchMode};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+/// Metrics collected since the server has been started.
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// Upper bounds of the buckets of request and search latencies in seconds.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Upper bounds of the buckets of the number of hits of searches.
+const HITS_BUCKETS: &[f64] = &[0.0, 1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0];
+
+/// Upper bounds of the buckets of scrape durations in seconds.
+const SCRAPE_BUCKETS: &[f64] = &[
+    1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0,
+];
+
+/// Counters and histograms of requests, searches and scrapes, which are
+/// rendered in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Request latencies by method, route and status.
+    requests: BTreeMap<(String, String, u16), Histogram>,
+    /// Search latencies and hits by backend and search mode.
+    searches: BTreeMap<(&'static str, &'static str), (Histogram, Histogram)>,
+    /// Scrape durations and fetched pages by user and kind.
+    scrapes: BTreeMap<(String, &'static str), (Histogram, u64)>,
+    /// Number of finished scrapes by user, kind and final state.
+    scrape_results: BTreeMap<(String, &'static str, &'static str), u64>,
+    /// Number of READMEs fetched by user and whether one has been found.
+    readmes: BTreeMap<(String, &'static str), u64>,
+    /// The remaining GitHub API requests by user, as last reported by GitHub.
+    rate_limit_remaining: BTreeMap<String, u64>,
+}
+
+impl Metrics {
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, latency: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_insert_with(|| Histogram::new(LATENCY_BUCKETS))
+            .observe(latency.as_secs_f64());
+    }
+
+    pub fn observe_search(
+        &self,
+        backend: &'static str,
+        mode: SearchMode,
+        latency: Duration,
+        hits: usize,
+    ) {
+        let mut inner = self.inner.lock().unwrap();
+        let (latencies, hit_counts) = inner
+            .searches
+            .entry((backend, mode.as_str()))
+            .or_insert_with(|| {
+                (
+                    Histogram::new(LATENCY_BUCKETS),
+                    Histogram::new(HITS_BUCKETS),
+                )
+            });
+        latencies.observe(latency.as_secs_f64());
+        hit_counts.observe(hits as f64);
+    }
+
+    /// Records the duration, fetched pages and outcome of the given finished
+    /// scrape job.
+    pub fn observe_scrape(&self, job: &Job) {
+        let kind = match job.kind {
+            JobKind::Fast => "fast",
+            JobKind::Full => "full",
+        };
+        let state = match job.state {
+            JobState::Succeeded => "succeeded",
+            JobState::Failed => "failed",
+            JobState::Queued | JobState::Running => return,
+        };
+        let duration = match (job.started_at, job.finished_at) {
+            (Some(started), Some(finished)) => {
+                (finished - started).num_milliseconds() as f64 / 1000.0
+            }
+            _ => 0.0,
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+        let (durations, pages) = inner
+            .scrapes
+            .entry((job.user.clone(), kind))
+            .or_insert_with(|| (Histogram::new(SCRAPE_BUCKETS), 0));
+        durations.observe(duration);
+        *pages += job.progress.pages_fetched as u64;
+
+        *inner
+            .scrape_results
+            .entry((job.user.clone(), kind, state))
+            .or_default() += 1;
+    }
+
+    pub fn count_readme(&self, user: &str, found: bool) {
+        let result = if found { "found" } else { "missing" };
+        let mut inner = self.inner.lock().unwrap();
+        *inner.readmes.entry((user.to_string(), result)).or_default() += 1;
+    }
+
+    pub fn set_rate_limit_remaining(&self, user: &str, remaining: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .rate_limit_remaining
+            .insert(user.to_string(), remaining);
+    }
+
+    /// Appends all collected metrics to the given output. Metrics of users
+    /// for which `include_user` returns false are left out.
+    pub fn render(&self, out: &mut String, include_user: impl Fn(&str) -> bool) {
+        let inner = self.inner.lock().unwrap();
+
+        write_header(
+            out,
+            "starsearch_http_requests_total",
+            "counter",
+            "Number of handled HTTP requests.",
+        );
+        for ((method, route, status), latencies) in &inner.requests {
+            let status = status.to_string();
+            let labels = [
+                ("method", method.as_str()),
+                ("route", route),
+                ("status", &status),
+            ];
+            write_sample(
+                out,
+                "starsearch_http_requests_total",
+                &labels,
+                latencies.count,
+            );
+        }
+
+        write_header(
+            out,
+            "starsearch_http_request_duration_seconds",
+            "histogram",
+            "Time taken to handle HTTP requests.",
+        );
+        for ((method, route, status), latencies) in &inner.requests {
+            let status = status.to_string();
+            let labels = [
+                ("method", method.as_str()),
+                ("route", route),
+                ("status", &status),
+            ];
+            latencies.write(out, "starsearch_http_request_duration_seconds", &labels);
+        }
+
+        write_header(
+            out,
+            "starsearch_search_duration_seconds",
+            "histogram",
+            "Time taken to search the index.",
+        );
+        for ((backend, mode), (latencies, _)) in &inner.searches {
+            let labels = [("backend", *backend), ("mode", mode)];
+            latencies.write(out, "starsearch_search_duration_seconds", &labels);
+        }
+
+        write_header(
+            out,
+            "starsearch_search_hits",
+            "histogram",
+            "Total number of hits of searches.",
+        );
+        for ((backend, mode), (_, hits)) in &inner.searches {
+            let labels = [("backend", *backend), ("mode", mode)];
+            hits.write(out, "starsearch_search_hits", &labels);
+        }
+
+        write_header(
+            out,
+            "starsearch_scrapes_total",
+            "counter",
+            "Number of finished scrapes by their final state.",
+        );
+        for ((user, kind, state), count) in &inner.scrape_results {
+            if !include_user(user) {
+                continue;
+            }
+            let labels = [("user", user.as_str()), ("kind", kind), ("state", state)];
+            write_sample(out, "starsearch_scrapes_total", &labels, count);
+        }
+
+        write_header(
+            out,
+            "starsearch_scrape_duration_seconds",
+            "histogram",
+            "Time taken by finished scrapes.",
+        );
+        for ((user, kind), (durations, _)) in &inner.scrapes {
+            if !include_user(user) {
+                continue;
+            }
+            let labels = [("user", user.as_str()), ("kind", kind)];
+            durations.write(out, "starsearch_scrape_duration_seconds", &labels);
+        }
+
+        write_header(
+            out,
+            "starsearch_scrape_pages_total",
+            "counter",
+            "Number of pages of starred repositories fetched by finished scrapes.",
+        );
+        for ((user, kind), (_, pages)) in &inner.scrapes {
+            if !include_user(user) {
+                continue;
+            }
+            let labels = [("user", user.as_str()), ("kind", kind)];
+            write_sample(out, "starsearch_scrape_pages_total", &labels, pages);
+        }
+
+        write_header(
+            out,
+            "starsearch_scrape_readmes_total",
+            "counter",
+            "Number of READMEs fetched, by whether the repository has one.",
+        );
+        for ((user, result), count) in &inner.readmes {
+            if !include_user(user) {
+                continue;
+            }
+            let labels = [("user", user.as_str()), ("result", result)];
+            write_sample(out, "starsearch_scrape_readmes_total", &labels, count);
+        }
+
+        write_header(
+            out,
+            "starsearch_github_rate_limit_remaining",
+            "gauge",
+            "Remaining GitHub API requests as last reported by GitHub.",
+        );
+        for (user, remaining) in &inner.rate_limit_remaining {
+            if !include_user(user) {
+                continue;
+            }
+            let labels = [("user", user.as_str())];
+            write_sample(
+                out,
+                "starsearch_github_rate_limit_remaining",
+                &labels,
+                remaining,
+            );
+        }
+    }
+}
+
+/// Cumulative histogram of observed values.
+struct Histogram {
+    bounds: &'static [f64],
+    /// Number of observations less than or equal to each bound.
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: vec![0; bounds.len()],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, bucket) in self.bounds.iter().zip(&mut self.buckets) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &[(&str, &str)]) {
+        let bucket_name = format!("{name}_bucket");
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            let le = bound.to_string();
+            let labels: Vec<_> = labels
+                .iter()
+                .copied()
+                .chain([("le", le.as_str())])
+                .collect();
+            write_sample(out, &bucket_name, &labels, bucket);
+        }
+        let labels_inf: Vec<_> = labels.iter().copied().chain([("le", "+Inf")]).collect();
+        write_sample(out, &bucket_name, &labels_inf, self.count);
+        write_sample(out, &format!("{name}_sum"), labels, self.sum);
+        write_sample(out, &format!("{name}_count"), labels, self.count);
+    }
+}
+
+/// Appends the HELP and TYPE lines of a metric family.
+pub fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Appends a sample of a metric with the given labels.
+pub fn write_sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: impl Display) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels: Vec<_> = labels
+            .iter()
+            .map(|(key, value)| format!("{key}=\"{}\"", escape_label(value)))
+            .collect();
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+    let _ = writeln!(out, " {value}");
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_write() {
+        let mut histogram = Histogram::new(&[0.5, 1.0]);
+        histogram.observe(0.25);
+        histogram.observe(1.0);
+        histogram.observe(2.0);
+
+        let mut out = String::new();
+        histogram.write(&mut out, "duration", &[("route", "/")]);
+        assert_eq!(
+            out,
+            "duration_bucket{route=\"/\",le=\"0.5\"} 1\n\
+             duration_bucket{route=\"/\",le=\"1\"} 2\n\
+             duration_bucket{route=\"/\",le=\"+Inf\"} 3\n\
+             duration_sum{route=\"/\"} 3.25\n\
+             duration_count{route=\"/\"} 3\n"
+        );
+    }
+
+    #[test]
+    fn write_sample_without_labels() {
+        let mut out = String::new();
+        write_sample(&mut out, "up", &[], 1);
+        assert_eq!(out, "up 1\n");
+    }
+
+    #[test]
+    fn escape_label_values() {
+        assert_eq!(escape_label(r#"a\b"c"#), r#"a\\b\"c"#);
+        assert_eq!(escape_label("a\nb"), r"a\nb");
+        assert_eq!(escape_label("plain"), "plain");
+    }
+
+    #[test]
+    fn render_requests() {
+        let metrics = Metrics::default();
+        metrics.observe_request("GET", "/api/search", 200, Duration::from_millis(20));
+        metrics.observe_request("GET", "/api/search", 200, Duration::from_millis(200));
+
+        let mut out = String::new();
+        metrics.render(&mut out, |_| true);
+        assert!(out.contains("# TYPE starsearch_http_requests_total counter\n"));
+        assert!(out.contains(
+            "starsearch_http_requests_total{method=\"GET\",route=\"/api/search\",status=\"200\"} 2\n"
+        ));
+        assert!(out.contains(
+            "starsearch_http_request_duration_seconds_bucket{method=\"GET\",\
+             route=\"/api/search\",status=\"200\",le=\"0.025\"} 1\n"
+        ));
+    }
+
+    #[test]
+    fn render_leaves_out_excluded_users() {
+        let metrics = Metrics::default();
+        metrics.count_readme("tester", true);
+        metrics.count_readme("alice", true);
+        metrics.set_rate_limit_remaining("alice", 4000);
+
+        let mut out = String::new();
+        metrics.render(&mut out, |user| user != "alice");
+        assert!(
+            out.contains("starsearch_scrape_readmes_total{user=\"tester\",result=\"found\"} 1\n")
+        );
+        assert!(!out.contains("alice"), "{out}");
+    }
+}
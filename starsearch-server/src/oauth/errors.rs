@@ -0,0 +1,12 @@
+use thiserror::Error;
+
+pub type Result<T, E = OAuthError> = core::result::Result<T, E>;
+
+#[derive(Error, Debug)]
+pub enum OAuthError {
+    #[error("request failed: {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("authorization failed: {0}")]
+    AuthorizationFailed(String),
+}
@@ -0,0 +1,126 @@
+pub mod errors;
+mod models;
+
+use crate::config::Config;
+use errors::{OAuthError, Result};
+use models::{AuthenticatedUser, TokenRequest, TokenResponse};
+use rand::RngCore;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, USER_AGENT};
+use reqwest::Url;
+
+/// Scopes requested from users. Listing private repositories requires full
+/// access to them, as GitHub offers no read-only scope.
+const SCOPES: &str = "repo read:user";
+
+/// Path of the callback route below the public URL of the web app.
+pub const CALLBACK_PATH: &str = "/login/callback";
+
+/// Client of the GitHub OAuth web application flow, through which users log
+/// in with their GitHub account.
+pub struct OAuth {
+    client_id: String,
+    client_secret: String,
+    github_url: String,
+    api_url: String,
+    /// Callback URL passed to GitHub. If not set, GitHub redirects to the
+    /// callback URL registered with the OAuth app.
+    redirect_uri: Option<String>,
+    client: reqwest::Client,
+}
+
+impl OAuth {
+    /// Returns the OAuth client of the app configured in the given config,
+    /// or `None` if login is not configured.
+    pub fn from_config(cfg: &Config) -> Result<Option<Self>> {
+        let (Some(client_id), Some(client_secret)) =
+            (&cfg.github_client_id, &cfg.github_client_secret)
+        else {
+            return Ok(None);
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static("starsearch"));
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+
+        Ok(Some(Self {
+            client_id: client_id.clone(),
+            client_secret: client_secret.clone(),
+            github_url: cfg.github_url().trim_end_matches('/').into(),
+            api_url: cfg.github_api_url().trim_end_matches('/').into(),
+            redirect_uri: cfg
+                .public_url
+                .as_ref()
+                .map(|url| format!("{}{CALLBACK_PATH}", url.trim_end_matches('/'))),
+            client: reqwest::Client::builder()
+                .default_headers(headers)
+                .build()?,
+        }))
+    }
+
+    /// Returns the URL of GitHub's authorization page to which users are
+    /// redirected to log in. The given state is passed back to the callback.
+    pub fn authorize_url(&self, state: &str) -> String {
+        let mut url = Url::parse(&format!("{}/login/oauth/authorize", self.github_url))
+            .expect("GitHub URL is valid");
+        url.query_pairs_mut()
+            .append_pair("client_id", &self.client_id)
+            .append_pair("scope", SCOPES)
+            .append_pair("state", state);
+        if let Some(redirect_uri) = &self.redirect_uri {
+            url.query_pairs_mut()
+                .append_pair("redirect_uri", redirect_uri);
+        }
+        url.into()
+    }
+
+    /// Exchanges the code passed to the callback for an access token.
+    pub async fn exchange(&self, code: &str) -> Result<String> {
+        let req = TokenRequest {
+            client_id: &self.client_id,
+            client_secret: &self.client_secret,
+            code,
+            redirect_uri: self.redirect_uri.as_deref(),
+        };
+        let res: TokenResponse = self
+            .client
+            .post(format!("{}/login/oauth/access_token", self.github_url))
+            .form(&req)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        match res {
+            TokenResponse::Token { access_token } => Ok(access_token),
+            TokenResponse::Error {
+                error,
+                error_description,
+            } => Err(OAuthError::AuthorizationFailed(
+                error_description.unwrap_or(error),
+            )),
+        }
+    }
+
+    /// Returns the login of the user owning the given access token.
+    pub async fn login(&self, token: &str) -> Result<String> {
+        let user: AuthenticatedUser = self
+            .client
+            .get(format!("{}/user", self.api_url))
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(user.login)
+    }
+}
+
+/// Generates the random value of the `state` parameter, which binds the
+/// callback to the browser that started the login.
+pub fn random_state() -> String {
+    let mut state = [0u8; 16];
+    rand::rng().fill_bytes(&mut state);
+    state.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Debug)]
+pub struct TokenRequest<'a> {
+    pub client_id: &'a str,
+    pub client_secret: &'a str,
+    pub code: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_uri: Option<&'a str>,
+}
+
+/// Response of the token endpoint, which responds with 200 even if the code
+/// has been rejected.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum TokenResponse {
+    Token {
+        access_token: String,
+    },
+    Error {
+        error: String,
+        error_description: Option<String>,
+    },
+}
+
+/// The authenticated user as returned by the `/user` endpoint.
+#[derive(Deserialize, Debug)]
+pub struct AuthenticatedUser {
+    pub login: String,
+}
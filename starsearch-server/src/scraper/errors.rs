@@ -8,6 +8,15 @@ pub enum ScraperError {
     #[error("request failed: {0}")]
     RequestError(#[from] reqwest::Error),
 
+    #[error("invalid content encoding: {0}")]
+    InvalidContent(#[from] base64::DecodeError),
+
+    #[error("graphql request failed: {0}")]
+    GraphQlError(String),
+
     #[error(transparent)]
     DatabaseError(#[from] DatabaseError),
+
+    #[error("task failed: {0}")]
+    TaskError(#[from] rocket::tokio::task::JoinError),
 }
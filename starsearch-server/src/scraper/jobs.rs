@@ -0,0 +1,102 @@
+use chrono::Local;
+use rocket::tokio::sync::watch;
+use starsearch_sdk::models::{Job, JobKind, JobProgress, JobState};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The number of jobs kept in memory, including the running ones.
+const MAX_JOBS: usize = 50;
+
+/// Registry of recent scrape jobs.
+#[derive(Default)]
+pub struct Jobs {
+    next_id: AtomicU64,
+    jobs: Mutex<VecDeque<JobHandle>>,
+}
+
+impl Jobs {
+    /// Registers a new queued job of the given kind scraping the stars of
+    /// the given user.
+    pub fn create(&self, kind: JobKind, user: &str) -> JobHandle {
+        let job = Job {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            kind,
+            user: user.into(),
+            state: JobState::Queued,
+            progress: JobProgress::default(),
+            errors: vec![],
+            created_at: Local::now(),
+            started_at: None,
+            finished_at: None,
+        };
+
+        let handle = JobHandle(Arc::new(watch::Sender::new(job)));
+
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.push_front(handle.clone());
+        jobs.truncate(MAX_JOBS);
+
+        handle
+    }
+
+    /// Returns all known jobs, the most recent one first.
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(JobHandle::get)
+            .collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<JobHandle> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|job| job.id() == id)
+            .cloned()
+    }
+}
+
+/// Shared handle to a job through which its progress is reported.
+#[derive(Clone)]
+pub struct JobHandle(Arc<watch::Sender<Job>>);
+
+impl JobHandle {
+    pub fn id(&self) -> u64 {
+        self.0.borrow().id
+    }
+
+    pub fn get(&self) -> Job {
+        self.0.borrow().clone()
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut Job)) {
+        self.0.send_modify(f);
+    }
+
+    pub fn error(&self, err: impl ToString) {
+        self.update(|job| job.errors.push(err.to_string()));
+    }
+
+    pub fn start(&self) {
+        self.update(|job| {
+            job.state = JobState::Running;
+            job.started_at = Some(Local::now());
+        });
+    }
+
+    pub fn finish(&self, state: JobState) {
+        self.update(|job| {
+            job.state = state;
+            job.finished_at = Some(Local::now());
+        });
+    }
+
+    /// Returns a receiver which is notified on every update of the job.
+    pub fn subscribe(&self) -> watch::Receiver<Job> {
+        self.0.subscribe()
+    }
+}
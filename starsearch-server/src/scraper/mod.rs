@@ -1,76 +1,281 @@
 #![allow(dead_code)]
 
 pub mod errors;
+pub mod jobs;
 pub mod models;
 
-use crate::db::Database;
-use crate::scraper::models::ContentEntry;
-use chrono::Local;
-use errors::Result;
+use crate::db::{CachedResponse, RequestCache, SearchBackend};
+use crate::embeddings::Embedder;
+use crate::metrics::METRICS;
+use crate::scraper::models::{
+    GraphQlRequest, GraphQlResponse, Readme, ReadmeEntry, StarListItemsData, StarListsData,
+    StarredRepository,
+};
+use chrono::{Local, Utc};
+use errors::{Result, ScraperError};
+use jobs::{JobHandle, Jobs};
 use log::{debug, info};
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, USER_AGENT};
-use reqwest::IntoUrl;
-use starsearch_sdk::models::Repository;
-use std::collections::HashSet;
-use std::sync::Arc;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT, AUTHORIZATION, ETAG, IF_NONE_MATCH, RETRY_AFTER,
+    USER_AGENT,
+};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use rocket::futures::stream::{self, StreamExt};
+use rocket::tokio::task;
+use rocket::tokio::time::{sleep_until, Instant};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use starsearch_sdk::models::{Job, JobKind, JobState, Repository};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const REPO_LIMIT: usize = 10_000;
+const INSERT_BATCH_SIZE: usize = 50;
+pub const DEFAULT_CONCURRENCY: usize = 4;
+const STAR_MEDIA_TYPE: &str = "application/vnd.github.star+json";
+
+const MAX_RATE_LIMIT_RETRIES: usize = 5;
+const DEFAULT_RATE_LIMIT_DELAY: Duration = Duration::from_secs(60);
+const MAX_RATE_LIMIT_DELAY: Duration = Duration::from_secs(3600);
+
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// Fetches the star lists of a user together with the first page of their
+/// items.
+const STAR_LISTS_QUERY: &str = r#"
+query($login: String!, $cursor: String) {
+  user(login: $login) {
+    lists(first: 100, after: $cursor) {
+      nodes {
+        id
+        name
+        items(first: 100) {
+          nodes { ... on Repository { databaseId } }
+          pageInfo { hasNextPage endCursor }
+        }
+      }
+      pageInfo { hasNextPage endCursor }
+    }
+  }
+}"#;
+
+/// Fetches further pages of the items of a star list.
+const STAR_LIST_ITEMS_QUERY: &str = r#"
+query($id: ID!, $cursor: String) {
+  node(id: $id) {
+    ... on UserList {
+      items(first: 100, after: $cursor) {
+        nodes { ... on Repository { databaseId } }
+        pageInfo { hasNextPage endCursor }
+      }
+    }
+  }
+}"#;
+
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+enum Conditional {
+    Modified(Response),
+    NotModified,
+}
 
 pub struct Scraper {
     github_username: String,
-    client: reqwest::Client,
-    db: Arc<Database>,
+    client: RwLock<reqwest::Client>,
+    /// Base URL of the GitHub REST and GraphQL APIs.
+    api_url: String,
+    /// Whether requests are sent with an API token, which is required by
+    /// the GraphQL API.
+    authenticated: bool,
+    /// Whether the stars of the token's owner are scraped, including private
+    /// repositories, instead of the public stars of the user.
+    private: bool,
+    db: Arc<dyn SearchBackend>,
+    concurrency: usize,
+    /// Point in time until which no requests must be sent to GitHub. This
+    /// is shared between all concurrently running requests so that a rate
+    /// limit hit by one of them pauses all others as well.
+    paused_until: Mutex<Option<Instant>>,
+    /// Registry of the jobs of this scraper, which may be shared with the
+    /// scrapers of other users.
+    jobs: Arc<Jobs>,
+    schedule: Mutex<Schedule>,
+    /// Computes the embeddings of scraped repositories if semantic search
+    /// is enabled.
+    embedder: Option<Arc<Embedder>>,
+}
+
+/// The currently running scrape job and the one queued after it.
+#[derive(Default)]
+struct Schedule {
+    running: Option<JobHandle>,
+    pending: Option<JobHandle>,
+}
+
+/// How a requested scrape is handled, depending on the scheduled jobs.
+#[derive(Debug, PartialEq)]
+enum StartAction {
+    /// Starts a job right away, as none is running.
+    Run,
+    /// Queues a job to run after the running one.
+    Queue,
+    /// Returns the running job, which covers the requested kind.
+    JoinRunning,
+    /// Returns the queued job, which is a full scrape.
+    JoinPending,
+}
+
+/// Decides how a scrape of the given kind is handled while a job of the
+/// given kind is running and another one may be queued.
+fn start_action(kind: JobKind, running: Option<JobKind>, pending: bool) -> StartAction {
+    match running {
+        _ if pending => StartAction::JoinPending,
+        None => StartAction::Run,
+        Some(JobKind::Full) => StartAction::JoinRunning,
+        Some(_) if kind == JobKind::Fast => StartAction::JoinRunning,
+        Some(_) => StartAction::Queue,
+    }
 }
 
 impl Scraper {
     pub fn new<S: Into<String>>(
         github_username: S,
         apitoken: Option<S>,
-        db: Arc<Database>,
+        db: Arc<dyn SearchBackend>,
+        jobs: Arc<Jobs>,
+        concurrency: usize,
+        embedder: Option<Arc<Embedder>>,
     ) -> Result<Self> {
-        let mut headers = HeaderMap::new();
-        headers.insert(USER_AGENT, HeaderValue::from_static("starsearch-scraper"));
-        if let Some(apitoken) = apitoken {
-            headers.insert(
-                AUTHORIZATION,
-                format!("Bearer {}", apitoken.into()).parse().unwrap(),
-            );
-        }
-
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()?;
+        let apitoken = apitoken.map(Into::into);
+        let authenticated = apitoken.is_some();
+        let client = build_client(apitoken.as_deref())?;
 
         Ok(Self {
             github_username: github_username.into(),
-            client,
+            client: RwLock::new(client),
+            api_url: DEFAULT_API_URL.into(),
+            authenticated,
+            private: false,
             db,
+            concurrency: concurrency.max(1),
+            paused_until: Mutex::new(None),
+            jobs,
+            schedule: Mutex::default(),
+            embedder,
         })
     }
 
-    pub async fn get_starred_repos(&self, only_new: bool) -> Result<Vec<Repository>> {
+    /// Sends all requests to the GitHub API at the given base URL instead of
+    /// the public one.
+    pub fn with_api_url(mut self, api_url: impl Into<String>) -> Self {
+        self.api_url = api_url.into().trim_end_matches('/').into();
+        self
+    }
+
+    /// Scrapes the stars of the owner of the API token, which include the
+    /// private repositories they have access to. The configured username
+    /// must be the token owner's.
+    pub fn with_private_stars(mut self) -> Self {
+        self.private = true;
+        self
+    }
+
+    /// Replaces the API token, e.g. when a user logs in again. Requests
+    /// which are already running keep the previous token.
+    pub fn set_token(&self, apitoken: &str) -> Result<()> {
+        *self.client.write().unwrap() = build_client(Some(apitoken))?;
+        Ok(())
+    }
+
+    fn client(&self) -> reqwest::Client {
+        self.client.read().unwrap().clone()
+    }
+
+    /// Requests a scrape of the given kind and returns the job performing
+    /// it without waiting for the job to finish.
+    ///
+    /// Scrapes never run concurrently. When a scrape is already running and
+    /// it covers the requested kind, the running job is returned. Otherwise
+    /// a job is queued which runs after the current one. A queued job always
+    /// is a full scrape, so further requests are absorbed by it.
+    pub fn start(self: &Arc<Self>, kind: JobKind) -> Job {
+        let mut schedule = self.schedule.lock().unwrap();
+
+        let running = schedule.running.as_ref().map(|job| job.get().kind);
+        match start_action(kind, running, schedule.pending.is_some()) {
+            StartAction::JoinPending => schedule.pending.as_ref().unwrap().get(),
+            StartAction::JoinRunning => schedule.running.as_ref().unwrap().get(),
+            StartAction::Queue => {
+                let job = self.jobs.create(kind, &self.github_username);
+                schedule.pending = Some(job.clone());
+                job.get()
+            }
+            StartAction::Run => {
+                let job = self.jobs.create(kind, &self.github_username);
+                schedule.running = Some(job.clone());
+                let scheduled = job.get();
+
+                let scraper = self.clone();
+                rocket::tokio::spawn(scraper.run_jobs(job));
+
+                scheduled
+            }
+        }
+    }
+
+    /// Runs the given job and afterwards all jobs queued in the meantime.
+    async fn run_jobs(self: Arc<Self>, mut job: JobHandle) {
+        loop {
+            if let Err(err) = self.run_job(&job).await {
+                error!("Scraping job {} failed: {err}", job.id());
+            }
+
+            let mut schedule = self.schedule.lock().unwrap();
+            schedule.running = schedule.pending.take();
+            match &schedule.running {
+                Some(next) => job = next.clone(),
+                None => break,
+            }
+        }
+    }
+
+    async fn run_job(&self, job: &JobHandle) -> Result<()> {
+        job.start();
+        let kind = job.get().kind;
+        let res = self.index(kind == JobKind::Fast, job).await;
+
+        match &res {
+            Ok(()) => job.finish(JobState::Succeeded),
+            Err(err) => {
+                job.error(err);
+                job.finish(JobState::Failed);
+            }
+        }
+        METRICS.observe_scrape(&job.get());
+
+        res
+    }
+
+    pub async fn get_starred_repos(
+        &self,
+        only_new: bool,
+        cache: &Mutex<RequestCache>,
+        job: &JobHandle,
+    ) -> Result<Vec<Repository>> {
         let mut page = 1;
         let mut repos = vec![];
 
         debug!("Scraping starred repos of user {}", &self.github_username);
 
-        let indexed_repos: HashSet<_> = self.db.list_ids().await?;
+        let indexed_repos: HashSet<_> = self.db.list_ids().await?.into_iter().collect();
 
         'outer: loop {
             debug!("Scraping page {} ...", &page);
 
-            let mut res: Vec<Repository> = self
-                .client
-                .get(format!(
-                    "https://api.github.com/users/{}/starred",
-                    &self.github_username
-                ))
-                .query(&[("page", &page)])
-                .send()
-                .await?
-                .error_for_status()?
-                .json()
-                .await?;
+            let mut res = self.get_starred_page(page, cache).await?;
+            job.update(|j| j.progress.pages_fetched += 1);
 
             if res.is_empty() {
                 break;
@@ -99,89 +304,465 @@ impl Scraper {
         Ok(repos)
     }
 
-    pub async fn get_file_contents<U: IntoUrl>(&self, url: U) -> Result<Option<String>> {
-        let res = self.client.get(url).send().await?;
+    async fn get_starred_page(
+        &self,
+        page: usize,
+        cache: &Mutex<RequestCache>,
+    ) -> Result<Vec<Repository>> {
+        let url = match self.private {
+            true => format!("{}/user/starred?page={page}", self.api_url),
+            false => format!(
+                "{}/users/{}/starred?page={page}",
+                self.api_url, &self.github_username
+            ),
+        };
+        let request = || self.client().get(&url).header(ACCEPT, STAR_MEDIA_TYPE);
+
+        let cached = cache.lock().unwrap().get(&url).cloned();
+        let res = self
+            .send_conditional(request(), cached.as_ref().map(|c| c.etag.as_str()))
+            .await?;
 
-        let res = if res.status().is_success() { Some(res.text().await?) } else { None };
+        let res = match (res, cached) {
+            (Conditional::Modified(res), _) => res,
+            (Conditional::NotModified, Some(cached)) => {
+                // The page did not change since the last scrape, so the
+                // repositories can be taken from the index as long as all of
+                // them are still present.
+                let mut stored: HashMap<_, _> = self
+                    .db
+                    .get_repos(&cached.ids)
+                    .await?
+                    .into_iter()
+                    .map(|r| (r.id, r))
+                    .collect();
+                if stored.len() == cached.ids.len() {
+                    debug!("Page {page} has not been modified");
+                    let repos = cached
+                        .ids
+                        .iter()
+                        .filter_map(|id| stored.remove(id))
+                        .collect();
+                    return Ok(repos);
+                }
+                self.send(request()).await?
+            }
+            (Conditional::NotModified, None) => self.send(request()).await?,
+        };
+
+        let etag = etag_header(&res);
+        let res: Vec<StarredRepository> = res.error_for_status()?.json().await?;
+        let repos: Vec<_> = res.into_iter().map(Repository::from).collect();
+
+        if let Some(etag) = etag {
+            cache.lock().unwrap().insert(
+                url,
+                CachedResponse {
+                    etag,
+                    ids: repos.iter().map(|r| r.id).collect(),
+                },
+            );
+        }
 
-        Ok(res)
+        Ok(repos)
     }
 
-    pub async fn get_readme_content(&self, owner: &str, repo: &str) -> Result<Option<String>> {
-        // First, try the default path for READMEs. This should match like 95% of the
-        // cases so we can save some API calls.
-        debug!("Trying to get README.md content for {owner}/{repo}...");
-        let res = self
-            .get_file_contents(format!(
-                "https://raw.githubusercontent.com/{owner}/{repo}/master/README.md"
-            ))
-            .await?;
+    /// Fetches the star lists of the user and returns the names of the lists
+    /// containing each repository by its ID. Star lists are only exposed by
+    /// the GraphQL API, so `None` is returned if no API token is configured.
+    pub async fn get_star_lists(&self) -> Result<Option<HashMap<u32, Vec<String>>>> {
+        if !self.authenticated {
+            debug!("Skipping star lists, which require an API token");
+            return Ok(None);
+        }
+
+        debug!("Fetching star lists of user {}", &self.github_username);
 
-        if let Some(res) = res {
-            debug!("Found README.md for {owner}/{repo}");
-            return Ok(Some(res));
+        let mut memberships: HashMap<u32, Vec<String>> = HashMap::new();
+        let mut cursor = None;
+
+        loop {
+            let variables = json!({ "login": &self.github_username, "cursor": cursor });
+            let data: StarListsData = self.graphql(STAR_LISTS_QUERY, variables).await?;
+            let Some(user) = data.user else {
+                break;
+            };
+
+            for list in user.lists.nodes {
+                let mut items = list.items;
+                loop {
+                    for id in items.nodes.iter().filter_map(|item| item.database_id) {
+                        memberships.entry(id).or_default().push(list.name.clone());
+                    }
+                    if !items.page_info.has_next_page {
+                        break;
+                    }
+
+                    let variables = json!({ "id": &list.id, "cursor": items.page_info.end_cursor });
+                    let data: StarListItemsData =
+                        self.graphql(STAR_LIST_ITEMS_QUERY, variables).await?;
+                    match data.node {
+                        Some(node) => items = node.items,
+                        None => break,
+                    }
+                }
+            }
+
+            if !user.lists.page_info.has_next_page {
+                break;
+            }
+            cursor = user.lists.page_info.end_cursor;
         }
 
-        debug!("Fetching repository contents for {owner}/{repo} ...");
-        let res: Vec<ContentEntry> = self
-            .client
-            .get(format!(
-                "https://api.github.com/repos/{owner}/{repo}/contents"
-            ))
-            .query(&[("per_page", "100")])
-            .send()
+        info!("Fetched star lists of {} repositories", memberships.len());
+
+        Ok(Some(memberships))
+    }
+
+    async fn graphql<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<T> {
+        let req = self
+            .client()
+            .post(format!("{}/graphql", self.api_url))
+            .json(&GraphQlRequest { query, variables });
+        let res: GraphQlResponse<T> = self.send(req).await?.error_for_status()?.json().await?;
+
+        if let Some(err) = res.errors.into_iter().next() {
+            return Err(ScraperError::GraphQlError(err.message));
+        }
+        res.data
+            .ok_or_else(|| ScraperError::GraphQlError("response contains no data".into()))
+    }
+
+    /// Fetches the README of the given repository from its default branch.
+    ///
+    /// When a `previous` README is passed and it has not been modified since
+    /// the last fetch, the previous README is returned.
+    pub async fn get_readme(
+        &self,
+        owner: &str,
+        repo: &str,
+        previous: Option<Readme>,
+        cache: &Mutex<RequestCache>,
+    ) -> Result<Option<Readme>> {
+        debug!("Fetching README for {owner}/{repo} ...");
+
+        let url = format!("{}/repos/{owner}/{repo}/readme", self.api_url);
+        let etag = previous
+            .as_ref()
+            .and_then(|_| cache.lock().unwrap().get(&url).map(|c| c.etag.clone()));
+
+        let res = match self
+            .send_conditional(self.client().get(&url), etag.as_deref())
             .await?
-            .error_for_status()?
-            .json()
-            .await?;
+        {
+            Conditional::Modified(res) => res,
+            Conditional::NotModified => {
+                debug!("README of {owner}/{repo} has not been modified");
+                return Ok(previous);
+            }
+        };
 
-        let readme_entry = res
-            .iter()
-            .find(|v| v.name.to_lowercase().starts_with("readme"));
+        if res.status() == StatusCode::NOT_FOUND {
+            debug!("No readme found for {owner}/{repo}");
+            return Ok(None);
+        }
+
+        let etag = etag_header(&res);
+        let entry: ReadmeEntry = res.error_for_status()?.json().await?;
+        let readme = Readme::try_from(entry)?;
+
+        if let Some(etag) = etag {
+            cache
+                .lock()
+                .unwrap()
+                .insert(url, CachedResponse { etag, ids: vec![] });
+        }
+
+        Ok(Some(readme))
+    }
+
+    /// Sends the given request. When GitHub responds with a rate limit error,
+    /// the request is retried after the rate limit has been reset.
+    ///
+    /// While a rate limit is in effect, all requests sent by this scraper are
+    /// held back until it has been reset.
+    async fn send(&self, req: RequestBuilder) -> Result<Response> {
+        let mut retries = 0;
+
+        loop {
+            self.wait_for_rate_limit().await;
+
+            let res = req
+                .try_clone()
+                .expect("request body must not be a stream")
+                .send()
+                .await?;
+
+            if let Some(remaining) = header_u64(res.headers(), &RATELIMIT_REMAINING) {
+                METRICS.set_rate_limit_remaining(&self.github_username, remaining);
+            }
+
+            let status = res.status();
+            let delay = rate_limit_delay(res.headers());
+            let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+                || (status == StatusCode::FORBIDDEN && delay.is_some());
+
+            if rate_limited && retries < MAX_RATE_LIMIT_RETRIES {
+                let delay = delay.unwrap_or(DEFAULT_RATE_LIMIT_DELAY);
+                warn!(
+                    "Rate limited by GitHub; retrying in {} seconds",
+                    delay.as_secs()
+                );
+                self.pause(delay);
+                retries += 1;
+                continue;
+            }
 
-        if let Some(download_url) = readme_entry.and_then(|v| v.download_url.as_ref()) {
-            debug!("Downloading README for {owner}/{repo} ...");
-            let res = self.get_file_contents(download_url).await?;
-            if let Some(res) = res {
-                return Ok(Some(res));
+            if let (false, Some(delay)) = (rate_limited, delay) {
+                // The request succeeded, but the rate limit is exhausted
+                // now, so wait until it resets before sending the next one.
+                info!(
+                    "GitHub rate limit exhausted; pausing for {} seconds",
+                    delay.as_secs()
+                );
+                self.pause(delay);
             }
+
+            return Ok(res);
         }
+    }
 
-        debug!("No readme found for {owner}/{repo}");
-        Ok(None)
+    /// Holds back all requests for the given duration. An already running
+    /// pause is only ever extended.
+    fn pause(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        let mut paused_until = self.paused_until.lock().unwrap();
+        if paused_until.is_none_or(|current| current < until) {
+            *paused_until = Some(until);
+        }
     }
 
-    pub async fn index(&self, fast: bool) -> Result<()> {
-        let mut repos = self.get_starred_repos(fast).await?;
+    async fn wait_for_rate_limit(&self) {
+        let paused_until = *self.paused_until.lock().unwrap();
+        if let Some(until) = paused_until {
+            sleep_until(until).await;
+        }
+    }
 
-        repos.retain(|r| !r.disabled);
+    async fn send_conditional(
+        &self,
+        mut req: RequestBuilder,
+        etag: Option<&str>,
+    ) -> Result<Conditional> {
+        if let Some(etag) = etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
 
-        for repository in repos.iter_mut() {
-            match self
-                .get_readme_content(&repository.owner.login, &repository.name)
-                .await
-            {
-                Ok(content) => repository.readme_content = content,
-                Err(err) => error!("failed getting readme content: {err}"),
+        let res = self.send(req).await?;
+        if res.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Conditional::NotModified);
+        }
+
+        Ok(Conditional::Modified(res))
+    }
+
+    /// Fetches the README of the given repository and sets it. When fetching
+    /// fails, the previously indexed README is kept.
+    async fn with_readme(
+        &self,
+        mut repository: Repository,
+        cache: &Mutex<RequestCache>,
+        job: &JobHandle,
+    ) -> Repository {
+        let previous = take_readme(&mut repository);
+
+        let readme = match self
+            .get_readme(
+                &repository.owner.login,
+                &repository.name,
+                previous.clone(),
+                cache,
+            )
+            .await
+        {
+            Ok(readme) => {
+                METRICS.count_readme(&self.github_username, readme.is_some());
+                readme
             }
+            Err(err) => {
+                error!("failed getting readme content: {err}");
+                job.error(format!("{}: {err}", repository.full_name));
+                previous
+            }
+        };
+        job.update(|j| j.progress.readmes_fetched += 1);
+
+        if let Some(readme) = readme {
+            repository.readme_path = Some(readme.path);
+            repository.readme_content = Some(readme.content);
         }
 
-        self.db.insert_repos(&repos).await?;
+        repository
+    }
 
+    /// Sets the READMEs stored in the index on the given repositories which
+    /// come without one, looking them up in a single request.
+    async fn with_stored_readmes(
+        &self,
+        mut repositories: Vec<Repository>,
+        job: &JobHandle,
+    ) -> Vec<Repository> {
+        let has_readme = |r: &Repository| r.readme_path.is_some() && r.readme_content.is_some();
+        let ids: Vec<_> = repositories
+            .iter()
+            .filter(|r| !has_readme(r))
+            .map(|r| r.id)
+            .collect();
+        if ids.is_empty() {
+            return repositories;
+        }
+
+        let mut stored: HashMap<_, _> = match self.db.get_repos(&ids).await {
+            Ok(stored) => stored.into_iter().map(|r| (r.id, r)).collect(),
+            Err(err) => {
+                error!("failed getting stored repositories: {err}");
+                job.error(err);
+                return repositories;
+            }
+        };
+        for repository in repositories.iter_mut().filter(|r| !has_readme(r)) {
+            if let Some(readme) = stored.get_mut(&repository.id).and_then(take_readme) {
+                repository.readme_path = Some(readme.path);
+                repository.readme_content = Some(readme.content);
+            }
+        }
+
+        repositories
+    }
+
+    /// Embeds the given repositories on the blocking thread pool, as the
+    /// model runs on the CPU.
+    async fn with_embeddings(
+        &self,
+        mut repositories: Vec<Repository>,
+        job: &JobHandle,
+    ) -> Result<Vec<Repository>> {
+        let Some(embedder) = self.embedder.clone() else {
+            return Ok(repositories);
+        };
+
+        let (repositories, errors) = task::spawn_blocking(move || {
+            let mut errors = vec![];
+            for repository in &mut repositories {
+                match embedder.embed_repository(repository) {
+                    Ok(embedding) => repository.embedding = embedding,
+                    Err(err) => {
+                        error!("failed embedding repository: {err}");
+                        errors.push(format!("{}: {err}", repository.full_name));
+                    }
+                }
+            }
+            (repositories, errors)
+        })
+        .await?;
+        for err in errors {
+            job.error(err);
+        }
+
+        Ok(repositories)
+    }
+
+    pub async fn index(&self, fast: bool, job: &JobHandle) -> Result<()> {
+        let cache = Mutex::new(self.db.get_request_cache().await?);
+
+        let mut repos = self.get_starred_repos(fast, &cache, job).await?;
+        let starred: HashSet<_> = repos.iter().map(|r| r.id).collect();
+
+        // If the star lists are unavailable, repositories taken from the
+        // index keep their previous memberships.
+        let lists = match self.get_star_lists().await {
+            Ok(lists) => lists,
+            Err(err) => {
+                error!("failed getting star lists: {err}");
+                job.error(format!("star lists: {err}"));
+                None
+            }
+        };
+
+        // Disabled repositories are dropped by full scrapes with the rest of
+        // the live index, while fast scrapes have to remove them.
+        let disabled: Vec<_> = repos.iter().filter(|r| r.disabled).map(|r| r.id).collect();
+        if fast && !disabled.is_empty() {
+            self.db.remove(&disabled).await?;
+        }
+        repos.retain(|r| !r.disabled);
+        job.update(|j| j.progress.repos_total = Some(repos.len()));
+
+        // Full scrapes are written into a staging index which replaces the
+        // live index once complete, so that unstarred repositories are
+        // dropped and searches never see a partially updated index.
         if !fast {
-            let stored_repos: Vec<_> = self.db.list_ids().await?;
-            let removed_repos: Vec<_> = stored_repos
-                .into_iter()
-                .filter(|id| !repos.iter().any(|r| &r.id == id))
-                .collect();
-            if !removed_repos.is_empty() {
-                self.db.remove(&removed_repos).await?;
-                info!(
-                    "Removed {} unstarred repositories from index",
-                    removed_repos.len()
-                )
+            self.db.create_staging().await?;
+        }
+
+        let total = repos.len();
+        let mut indexed = 0;
+        let scraped_at = Utc::now();
+
+        // The previously indexed READMEs are looked up in batches. READMEs
+        // are then fetched by a bounded number of concurrent requests and the
+        // repositories are embedded and written to the index in batches as
+        // soon as they are complete.
+        let mut batches = stream::iter(repos)
+            .map(|mut repository| {
+                repository.scraped_at = Some(scraped_at);
+                if let Some(lists) = &lists {
+                    repository.lists = lists.get(&repository.id).cloned().unwrap_or_default();
+                }
+                repository
+            })
+            .chunks(INSERT_BATCH_SIZE)
+            .map(|repositories| self.with_stored_readmes(repositories, job))
+            .buffered(1)
+            .flat_map(stream::iter)
+            .map(|repository| self.with_readme(repository, &cache, job))
+            .buffer_unordered(self.concurrency)
+            .chunks(INSERT_BATCH_SIZE)
+            .map(|repositories| self.with_embeddings(repositories, job))
+            .buffered(1);
+
+        while let Some(batch) = batches.next().await {
+            let batch = batch?;
+            if fast {
+                self.db.insert_repos(&batch).await?;
+
+                let snapshot = cache.lock().unwrap().clone();
+                self.db.set_request_cache(&snapshot).await?;
+            } else {
+                // The request cache is only stored after the swap, as cached
+                // responses must match the contents of the live index.
+                self.db.insert_staging(&batch).await?;
             }
+
+            indexed += batch.len();
+            job.update(|j| j.progress.repos_indexed = indexed);
+            debug!("Indexed {indexed}/{total} repositories");
+        }
+        drop(batches);
+
+        let mut cache = cache.into_inner().unwrap();
+        if !fast {
+            self.db.swap_staging().await?;
+            info!("Replaced index with {total} freshly scraped repositories");
+            // Annotations are kept as long as the repository is starred.
+            self.db.retain_annotations(&starred).await?;
+            cache.retain_used();
         }
+        self.db.set_request_cache(&cache).await?;
 
         let now = Local::now();
         let mut index_dates = self.db.get_index_dates().await?;
@@ -194,3 +775,168 @@ impl Scraper {
         Ok(())
     }
 }
+
+/// Builds the client for the GitHub API, which sends the given API token
+/// with every request.
+fn build_client(apitoken: Option<&str>) -> Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static("starsearch-scraper"));
+    if let Some(apitoken) = apitoken {
+        headers.insert(AUTHORIZATION, format!("Bearer {apitoken}").parse().unwrap());
+    }
+
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()?;
+    Ok(client)
+}
+
+/// Takes the README stored in the given repository, if both its path and
+/// content are known.
+fn take_readme(repository: &mut Repository) -> Option<Readme> {
+    let path = repository.readme_path.take();
+    let content = repository.readme_content.take();
+    Some(Readme {
+        path: path?,
+        content: content?,
+    })
+}
+
+fn etag_header(res: &Response) -> Option<String> {
+    res.headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(Into::into)
+}
+
+fn header_u64(headers: &HeaderMap, name: &HeaderName) -> Option<u64> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+/// Returns the time to wait before the next request can be sent based on the
+/// `Retry-After` and `X-RateLimit-*` headers of a response.
+///
+/// Returns `None` when the rate limit is not exhausted.
+fn rate_limit_delay(headers: &HeaderMap) -> Option<Duration> {
+    let delay = if let Some(retry_after) = header_u64(headers, &RETRY_AFTER) {
+        Duration::from_secs(retry_after)
+    } else if header_u64(headers, &RATELIMIT_REMAINING) == Some(0) {
+        let reset = header_u64(headers, &RATELIMIT_RESET)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+        Duration::from_secs(reset.saturating_sub(now) + 1)
+    } else {
+        return None;
+    };
+
+    Some(delay.min(MAX_RATE_LIMIT_DELAY))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_map(values: &[(&HeaderName, String)]) -> HeaderMap {
+        values
+            .iter()
+            .map(|(name, value)| ((*name).clone(), value.parse().unwrap()))
+            .collect()
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn rate_limit_delay_is_none_while_requests_remain() {
+        let headers = header_map(&[
+            (&RATELIMIT_REMAINING, "10".into()),
+            (&RATELIMIT_RESET, (now() + 600).to_string()),
+        ]);
+        assert_eq!(rate_limit_delay(&headers), None);
+        assert_eq!(rate_limit_delay(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn rate_limit_delay_prefers_retry_after() {
+        let headers = header_map(&[
+            (&RETRY_AFTER, "120".into()),
+            (&RATELIMIT_REMAINING, "0".into()),
+            (&RATELIMIT_RESET, (now() + 600).to_string()),
+        ]);
+        assert_eq!(rate_limit_delay(&headers), Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn rate_limit_delay_waits_until_reset() {
+        let headers = header_map(&[
+            (&RATELIMIT_REMAINING, "0".into()),
+            (&RATELIMIT_RESET, (now() + 30).to_string()),
+        ]);
+        let delay = rate_limit_delay(&headers).unwrap();
+        assert!((Duration::from_secs(30)..=Duration::from_secs(31)).contains(&delay));
+
+        // A reset in the past still waits a second for the clocks to agree.
+        let headers = header_map(&[
+            (&RATELIMIT_REMAINING, "0".into()),
+            (&RATELIMIT_RESET, (now() - 30).to_string()),
+        ]);
+        assert_eq!(rate_limit_delay(&headers), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn rate_limit_delay_requires_reset_when_exhausted() {
+        let headers = header_map(&[(&RATELIMIT_REMAINING, "0".into())]);
+        assert_eq!(rate_limit_delay(&headers), None);
+    }
+
+    #[test]
+    fn rate_limit_delay_is_capped() {
+        let headers = header_map(&[(&RETRY_AFTER, "86400".into())]);
+        assert_eq!(rate_limit_delay(&headers), Some(MAX_RATE_LIMIT_DELAY));
+
+        let headers = header_map(&[
+            (&RATELIMIT_REMAINING, "0".into()),
+            (&RATELIMIT_RESET, (now() + 86400).to_string()),
+        ]);
+        assert_eq!(rate_limit_delay(&headers), Some(MAX_RATE_LIMIT_DELAY));
+    }
+
+    #[test]
+    fn start_runs_without_running_job() {
+        assert_eq!(start_action(JobKind::Fast, None, false), StartAction::Run);
+        assert_eq!(start_action(JobKind::Full, None, false), StartAction::Run);
+    }
+
+    #[test]
+    fn start_joins_running_job_covering_kind() {
+        for kind in [JobKind::Fast, JobKind::Full] {
+            let action = start_action(kind, Some(JobKind::Full), false);
+            assert_eq!(action, StartAction::JoinRunning);
+        }
+        let action = start_action(JobKind::Fast, Some(JobKind::Fast), false);
+        assert_eq!(action, StartAction::JoinRunning);
+    }
+
+    #[test]
+    fn start_queues_full_after_fast_job() {
+        let action = start_action(JobKind::Full, Some(JobKind::Fast), false);
+        assert_eq!(action, StartAction::Queue);
+    }
+
+    #[test]
+    fn start_joins_queued_job() {
+        for (kind, running) in [
+            (JobKind::Fast, JobKind::Fast),
+            (JobKind::Full, JobKind::Fast),
+        ] {
+            let action = start_action(kind, Some(running), true);
+            assert_eq!(action, StartAction::JoinPending);
+        }
+    }
+}
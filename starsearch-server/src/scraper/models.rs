@@ -1,7 +1,131 @@
+use super::errors::ScraperError;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use starsearch_sdk::models::Repository;
 
+/// Response of the `/repos/{owner}/{repo}/readme` endpoint.
 #[derive(Deserialize, Serialize, Debug)]
-pub struct ContentEntry {
+pub struct ReadmeEntry {
+    pub path: String,
+    pub content: String,
+    pub encoding: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Readme {
+    pub path: String,
+    pub content: String,
+}
+
+impl TryFrom<ReadmeEntry> for Readme {
+    type Error = ScraperError;
+
+    fn try_from(value: ReadmeEntry) -> Result<Self, Self::Error> {
+        let content = match value.encoding.as_str() {
+            "base64" => {
+                // GitHub wraps the encoded content into multiple lines.
+                let encoded: String = value
+                    .content
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .collect();
+                let decoded = BASE64_STANDARD.decode(encoded)?;
+                String::from_utf8_lossy(&decoded).into_owned()
+            }
+            _ => value.content,
+        };
+
+        Ok(Self {
+            path: value.path,
+            content,
+        })
+    }
+}
+
+/// Entry of the starred repositories list when requested with the
+/// `application/vnd.github.star+json` media type.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct StarredRepository {
+    pub starred_at: DateTime<Utc>,
+    pub repo: Repository,
+}
+
+impl From<StarredRepository> for Repository {
+    fn from(value: StarredRepository) -> Self {
+        Self {
+            starred_at: Some(value.starred_at),
+            ..value.repo
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct GraphQlRequest<'a> {
+    pub query: &'a str,
+    pub variables: Value,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GraphQlResponse<T> {
+    pub data: Option<T>,
+    #[serde(default)]
+    pub errors: Vec<GraphQlError>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GraphQlError {
+    pub message: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Connection<T> {
+    pub nodes: Vec<T>,
+    pub page_info: PageInfo,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    pub end_cursor: Option<String>,
+}
+
+/// Response data of the star lists query.
+#[derive(Deserialize, Debug)]
+pub struct StarListsData {
+    pub user: Option<StarListsUser>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StarListsUser {
+    pub lists: Connection<StarList>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StarList {
+    pub id: String,
     pub name: String,
-    pub download_url: Option<String>,
+    pub items: Connection<StarListItem>,
+}
+
+/// Item of a star list. Lists can only contain repositories, but the items
+/// are typed as a union, so the ID is missing for any other type.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StarListItem {
+    pub database_id: Option<u32>,
+}
+
+/// Response data of the star list items query.
+#[derive(Deserialize, Debug)]
+pub struct StarListItemsData {
+    pub node: Option<StarListItems>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StarListItems {
+    pub items: Connection<StarListItem>,
 }
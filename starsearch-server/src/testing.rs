@@ -0,0 +1,104 @@
+//! Fixtures shared by the tests of several modules.
+
+use crate::config::{Backend, Config};
+use chrono::{TimeZone, Utc};
+use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+use rocket::tokio::net::{TcpListener, TcpStream};
+use serde_json::json;
+use starsearch_sdk::models::Repository;
+use std::path::Path;
+
+/// Returns a config storing all data with the embedded backend in the given
+/// directory. GitHub is unreachable, so scrapes fail right away.
+pub fn config(data_dir: &Path) -> Config {
+    let mut cfg: Config = serde_json::from_value(json!({
+        "github_url": "http://127.0.0.1:9",
+        "github_api_url": "http://127.0.0.1:9",
+    }))
+    .unwrap();
+    cfg.search_backend = Some(Backend::Tantivy);
+    cfg.data_dir = Some(data_dir.to_string_lossy().into());
+    cfg
+}
+
+/// Returns a repository with the given full name, which has been starred the
+/// given number of days after the start of 2024.
+pub fn repository(id: u32, full_name: &str, starred_day: u32) -> Repository {
+    let (owner, name) = full_name.split_once('/').unwrap();
+    let date = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+    let mut repo: Repository = serde_json::from_value(json!({
+        "id": id,
+        "name": name,
+        "full_name": full_name,
+        "owner": { "id": id, "login": owner },
+        "description": null,
+        "fork": false,
+        "url": format!("https://api.github.com/repos/{full_name}"),
+        "html_url": format!("https://github.com/{full_name}"),
+        "created_at": date,
+        "updated_at": date,
+        "language": null,
+        "license": null,
+        "topics": null,
+        "readme_content": null,
+        "disabled": false,
+    }))
+    .unwrap();
+    repo.starred_at = Some(
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + chrono::Days::new(starred_day.into()),
+    );
+    repo
+}
+
+/// Serves HTTP requests on a local port with the status and JSON body the
+/// given handler returns for the head (request line and headers) and the
+/// body of each request. Returns the URL of the server.
+pub async fn http_server<F>(handler: F) -> String
+where
+    F: Fn(&str, &str) -> (u16, String) + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    rocket::tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let Some((head, body)) = read_request(&mut stream).await else {
+                continue;
+            };
+            let (status, body) = handler(&head, &body);
+            let res = format!(
+                "HTTP/1.1 {status} Status\r\ncontent-type: application/json\r\n\
+                content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = stream.write_all(res.as_bytes()).await;
+        }
+    });
+    url
+}
+
+/// Reads the head and the body of a request of which the body is sized by
+/// its `content-length` header.
+async fn read_request(stream: &mut TcpStream) -> Option<(String, String)> {
+    let mut data = vec![];
+    let mut buf = [0; 4096];
+    loop {
+        let text = String::from_utf8_lossy(&data);
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            let length = head
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                .and_then(|(_, value)| value.trim().parse().ok())
+                .unwrap_or(0);
+            if body.len() >= length {
+                return Some((head.into(), body.into()));
+            }
+        }
+
+        let n = stream.read(&mut buf).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+}
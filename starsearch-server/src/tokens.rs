@@ -0,0 +1,111 @@
+use crate::config::Config;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+
+/// Access granted by an API token. Admin tokens grant read access as well.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Scope {
+    Read,
+    Admin,
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "admin" => Ok(Self::Admin),
+            _ => Err(format!("unknown scope '{s}', must be 'read' or 'admin'")),
+        }
+    }
+}
+
+/// The API tokens accepted by the server. Only the SHA-256 hashes of the
+/// tokens are configured, so the configuration does not reveal them.
+pub struct ApiTokens(Vec<(Scope, String)>);
+
+impl ApiTokens {
+    /// Parses the comma-separated `<scope>:<sha256 hash>` entries of the
+    /// configuration. Returns `None` if no tokens are configured.
+    pub fn from_config(cfg: &Config) -> Result<Option<Self>, String> {
+        match &cfg.api_tokens {
+            Some(entries) => Self::parse(entries),
+            None => Ok(None),
+        }
+    }
+
+    fn parse(entries: &str) -> Result<Option<Self>, String> {
+        let mut tokens = vec![];
+        for entry in entries.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (scope, hash) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("API token '{entry}' is missing its scope"))?;
+            let hash = hash.trim().to_lowercase();
+            if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("API token hash '{hash}' is no SHA-256 hash"));
+            }
+            tokens.push((scope.trim().parse()?, hash));
+        }
+
+        Ok((!tokens.is_empty()).then_some(Self(tokens)))
+    }
+
+    /// Returns the scope of the given token or `None` if it is unknown.
+    pub fn scope(&self, token: &str) -> Option<Scope> {
+        let hash = format!("{:x}", Sha256::digest(token));
+        self.0
+            .iter()
+            .filter(|(_, h)| *h == hash)
+            .map(|(scope, _)| *scope)
+            .max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(token: &str) -> String {
+        format!("{:x}", Sha256::digest(token))
+    }
+
+    #[test]
+    fn parse_scoped_hashes() {
+        let entries = format!(
+            " admin:{} , read:{},",
+            hash("secret").to_uppercase(),
+            hash("reader")
+        );
+        let tokens = ApiTokens::parse(&entries).unwrap().unwrap();
+        assert_eq!(tokens.scope("secret"), Some(Scope::Admin));
+        assert_eq!(tokens.scope("reader"), Some(Scope::Read));
+        assert_eq!(tokens.scope("unknown"), None);
+        assert_eq!(tokens.scope(&hash("secret")), None);
+    }
+
+    #[test]
+    fn parse_prefers_highest_scope() {
+        let entries = format!("read:{0},admin:{0}", hash("secret"));
+        let tokens = ApiTokens::parse(&entries).unwrap().unwrap();
+        assert_eq!(tokens.scope("secret"), Some(Scope::Admin));
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert!(ApiTokens::parse("").unwrap().is_none());
+        assert!(ApiTokens::parse(" , ").unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(ApiTokens::parse(&hash("secret")).is_err());
+        assert!(ApiTokens::parse("admin:abc").is_err());
+        assert!(ApiTokens::parse(&format!("write:{}", hash("secret"))).is_err());
+    }
+
+    #[test]
+    fn admin_scope_includes_read() {
+        assert!(Scope::Admin > Scope::Read);
+    }
+}
@@ -0,0 +1,58 @@
+use super::errors::Result;
+use crate::files;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// A user who logged in via GitHub OAuth.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Account {
+    pub login: String,
+    /// The OAuth token with which the user's stars are scraped.
+    pub token: String,
+}
+
+/// JSON file backed store of the accounts of all users who logged in, so
+/// that their stars keep being scraped after a restart.
+pub struct AccountStore {
+    path: PathBuf,
+    accounts: Mutex<Vec<Account>>,
+}
+
+impl AccountStore {
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let accounts = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            path,
+            accounts: Mutex::new(accounts),
+        })
+    }
+
+    pub fn list(&self) -> Vec<Account> {
+        self.accounts.lock().unwrap().clone()
+    }
+
+    /// Stores the given account, replacing a previously stored one of the
+    /// same user.
+    pub fn insert(&self, account: Account) -> Result<()> {
+        let mut accounts = self.accounts.lock().unwrap();
+        accounts.retain(|a| !a.login.eq_ignore_ascii_case(&account.login));
+        accounts.push(account);
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // The file holds GitHub tokens.
+        files::write_atomic(&self.path, &serde_json::to_vec(&*accounts)?, true)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+use crate::db::errors::DatabaseError;
+use crate::scraper::errors::ScraperError;
+use thiserror::Error;
+use tokio_cron_scheduler::JobSchedulerError;
+
+pub type Result<T, E = UserError> = core::result::Result<T, E>;
+
+#[derive(Error, Debug)]
+pub enum UserError {
+    #[error(transparent)]
+    DatabaseError(#[from] DatabaseError),
+
+    #[error(transparent)]
+    ScraperError(#[from] ScraperError),
+
+    #[error("scheduling failed: {0}")]
+    SchedulerError(#[from] JobSchedulerError),
+
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("invalid accounts file: {0}")]
+    InvalidAccounts(#[from] serde_json::Error),
+}
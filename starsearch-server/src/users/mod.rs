@@ -0,0 +1,552 @@
+pub mod accounts;
+pub mod errors;
+
+use self::accounts::{Account, AccountStore};
+use crate::config::Config;
+use crate::db::{self, top_facet_values, SearchBackend, MAX_TOTAL_HITS};
+use crate::embeddings::Embedder;
+use crate::scraper::jobs::Jobs;
+use crate::scraper::{self, Scraper};
+use errors::Result;
+use log::info;
+use rocket::futures::future::try_join_all;
+use rocket::tokio::sync::Mutex;
+use starsearch_sdk::models::{
+    FacetCount, Facets, JobKind, Repository, SearchOptions, SearchResponse, Sort,
+};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio_cron_scheduler::{Job, JobScheduler};
+
+const DEFAULT_SCRAPE_FAST_INTERVAL: u64 = 3500;
+const DEFAULT_SCRAPE_FULL_INTERVAL: u64 = 3600 * 12;
+
+/// File in the data directory holding the accounts of users who logged in.
+const ACCOUNTS_FILE: &str = "accounts.json";
+
+/// A user of which the stars are scraped into an index of their own.
+pub struct User {
+    pub name: String,
+    pub db: Arc<dyn SearchBackend>,
+    pub scraper: Arc<Scraper>,
+    /// Whether the user logged in via GitHub OAuth. Their stars are scraped
+    /// with their own token and may include private repositories, so only
+    /// they can access them.
+    pub private: bool,
+}
+
+impl User {
+    /// Whether the user's stars are accessible to the given logged in user.
+    /// Without login, only the stars of configured users are accessible.
+    pub fn is_visible_to(&self, viewer: Option<&str>) -> bool {
+        !self.private || viewer.is_some_and(|login| login.eq_ignore_ascii_case(&self.name))
+    }
+}
+
+/// The users of which the stars are searchable. These are the configured
+/// users, the first of which is the default one, and the users who logged in
+/// via GitHub OAuth.
+pub struct Users {
+    users: RwLock<Vec<Arc<User>>>,
+    accounts: AccountStore,
+    /// Serializes logins, so that concurrent first logins of a user add them
+    /// only once.
+    sign_ins: Mutex<()>,
+    /// Registry of the scrape jobs of all users.
+    jobs: Arc<Jobs>,
+    sched: JobScheduler,
+    cfg: Config,
+    embedder: Option<Arc<Embedder>>,
+}
+
+impl Users {
+    /// Creates an empty registry. The scrapes of added users are scheduled
+    /// with the given scheduler.
+    pub fn new(cfg: Config, embedder: Option<Arc<Embedder>>, sched: JobScheduler) -> Result<Self> {
+        let accounts = AccountStore::open(Path::new(cfg.data_dir()).join(ACCOUNTS_FILE))?;
+
+        Ok(Self {
+            users: RwLock::default(),
+            accounts,
+            sign_ins: Mutex::default(),
+            jobs: Arc::default(),
+            sched,
+            cfg,
+            embedder,
+        })
+    }
+
+    /// Adds a configured user, whose public stars are scraped with the
+    /// configured API token. The first configured user keeps the storage of
+    /// earlier versions, which only supported a single user.
+    pub async fn add(&self, name: &str) -> Result<Arc<User>> {
+        let first = !self.all().iter().any(|user| !user.private);
+        let namespace = (!first).then(|| name.to_lowercase());
+
+        let user = self
+            .create(
+                name,
+                self.cfg.github_apitoken.clone(),
+                namespace.as_deref(),
+                false,
+                true,
+            )
+            .await?;
+        Ok(self.insert(user))
+    }
+
+    /// Adds the users of all stored accounts.
+    pub async fn restore_accounts(&self) -> Result<()> {
+        for account in self.accounts.list() {
+            let user = self.create_private(&account, true).await?;
+            self.insert(user);
+        }
+        Ok(())
+    }
+
+    /// Stores the account of a user who logged in. On their first login, the
+    /// user is added and their stars are scraped. Later logins replace the
+    /// token their stars are scraped with.
+    pub async fn sign_in(&self, account: Account) -> Result<Arc<User>> {
+        let _guard = self.sign_ins.lock().await;
+        self.accounts.insert(account.clone())?;
+
+        if let Some(user) = self.account(&account.login) {
+            user.scraper.set_token(&account.token)?;
+            return Ok(user);
+        }
+
+        // The login fails right away instead of waiting for the backend.
+        info!("Adding user {} who logged in", account.login);
+        let user = self.insert(self.create_private(&account, false).await?);
+        user.scraper.start(JobKind::Fast);
+        Ok(user)
+    }
+
+    async fn create_private(&self, account: &Account, retry: bool) -> Result<User> {
+        // Logins are prefixed, so that a user's private index never clashes
+        // with the one of a configured user of the same name.
+        let namespace = format!("account_{}", account.login.to_lowercase());
+        self.create(
+            &account.login,
+            Some(account.token.clone()),
+            Some(&namespace),
+            true,
+            retry,
+        )
+        .await
+    }
+
+    /// Connects the backend of a user and schedules their scrapes. With
+    /// `retry` set, the connection is retried as on startup.
+    async fn create(
+        &self,
+        name: &str,
+        token: Option<String>,
+        namespace: Option<&str>,
+        private: bool,
+        retry: bool,
+    ) -> Result<User> {
+        let db = db::connect(&self.cfg, namespace, self.embedder.clone(), retry).await?;
+
+        let mut scraper = Scraper::new(
+            name.to_string(),
+            token,
+            db.clone(),
+            self.jobs.clone(),
+            self.cfg
+                .scrape_concurrency
+                .unwrap_or(scraper::DEFAULT_CONCURRENCY),
+            self.embedder.clone(),
+        )?
+        .with_api_url(self.cfg.github_api_url());
+        if private {
+            scraper = scraper.with_private_stars();
+        }
+        let scraper = Arc::new(scraper);
+
+        let fast_interval = self
+            .cfg
+            .scrape_fast_interval_seconds
+            .unwrap_or(DEFAULT_SCRAPE_FAST_INTERVAL);
+        let full_interval = self
+            .cfg
+            .scrape_full_interval_seconds
+            .unwrap_or(DEFAULT_SCRAPE_FULL_INTERVAL);
+        self.schedule(name, &scraper, JobKind::Fast, fast_interval)
+            .await?;
+        self.schedule(name, &scraper, JobKind::Full, full_interval)
+            .await?;
+
+        Ok(User {
+            name: name.to_string(),
+            db,
+            scraper,
+            private,
+        })
+    }
+
+    async fn schedule(
+        &self,
+        name: &str,
+        scraper: &Arc<Scraper>,
+        kind: JobKind,
+        interval_seconds: u64,
+    ) -> Result<()> {
+        let (name, scraper) = (name.to_string(), scraper.clone());
+        let kind_name = match kind {
+            JobKind::Fast => "fast",
+            JobKind::Full => "full",
+        };
+        let job =
+            Job::new_repeated_async(Duration::from_secs(interval_seconds), move |_uuid, _l| {
+                info!("Starting scheduled {kind_name} scraping of {name} ...");
+                let scraper = scraper.clone();
+                Box::pin(async move {
+                    scraper.start(kind);
+                })
+            })?;
+
+        self.sched.add(job).await?;
+        Ok(())
+    }
+
+    fn insert(&self, user: User) -> Arc<User> {
+        let user = Arc::new(user);
+        self.users.write().unwrap().push(user.clone());
+        user
+    }
+
+    /// Returns all users, including the ones who logged in.
+    pub fn all(&self) -> Vec<Arc<User>> {
+        self.users.read().unwrap().clone()
+    }
+
+    /// Returns the users whose stars are accessible to the given logged in
+    /// user. Their own stars come first, followed by the ones of the
+    /// configured users.
+    pub fn visible(&self, viewer: Option<&str>) -> Vec<Arc<User>> {
+        let mut users: Vec<_> = self
+            .all()
+            .into_iter()
+            .filter(|user| user.is_visible_to(viewer))
+            .collect();
+        users.sort_by_key(|user| !user.private);
+        users
+    }
+
+    /// Returns the user who logged in with the given login.
+    pub fn account(&self, login: &str) -> Option<Arc<User>> {
+        self.all()
+            .into_iter()
+            .find(|user| user.private && user.name.eq_ignore_ascii_case(login))
+    }
+
+    /// Returns the user with the given name, which is matched
+    /// case-insensitively like on GitHub, or the default user if no name is
+    /// given. Only users visible to the given logged in user are returned.
+    pub fn get(&self, name: Option<&str>, viewer: Option<&str>) -> Option<Arc<User>> {
+        let mut visible = self.visible(viewer).into_iter();
+        match name {
+            Some(name) => visible.find(|user| user.name.eq_ignore_ascii_case(name)),
+            None => visible.next(),
+        }
+    }
+
+    pub fn jobs(&self) -> &Jobs {
+        &self.jobs
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.cfg
+    }
+
+    /// Searches the stars of all users visible to the given logged in user,
+    /// or lists them if no query is given.
+    ///
+    /// The results of the users are merged by the requested sort order or,
+    /// without one, by their rank. Repositories starred by several users are
+    /// only returned once and name all of them in
+    /// [`Repository::starred_by`]. The total and the facet counts are summed
+    /// over all users, so they may count such repositories more than once.
+    /// Only the duplicates up to the end of the requested page are known and
+    /// subtracted from the total, so the last pages may come up short or
+    /// empty.
+    pub async fn search(
+        &self,
+        viewer: Option<&str>,
+        query: Option<&str>,
+        limit: usize,
+        offset: usize,
+        opts: &SearchOptions,
+    ) -> db::errors::Result<SearchResponse> {
+        let users = self.visible(viewer);
+
+        // The hits of every user up to the end of the requested page are
+        // needed to know which ones make it into the page.
+        let window = offset.saturating_add(limit).min(MAX_TOTAL_HITS);
+        let responses = try_join_all(users.iter().map(|user| async move {
+            match query {
+                Some(query) => user.db.search(query, window, 0, opts).await,
+                None => user.db.list(window, 0, opts).await,
+            }
+        }))
+        .await?;
+
+        let names: Vec<_> = users.iter().map(|user| user.name.as_str()).collect();
+        Ok(merge_responses(
+            &names,
+            responses,
+            query.is_none(),
+            limit,
+            offset,
+            opts,
+        ))
+    }
+}
+
+/// Merges the responses of the searches of the users with the given names
+/// into the requested page, as described at [`Users::search`]. The responses
+/// hold the hits of each user up to the end of the page, either of a search
+/// or of a listing.
+fn merge_responses(
+    names: &[&str],
+    responses: Vec<SearchResponse>,
+    listing: bool,
+    limit: usize,
+    offset: usize,
+    opts: &SearchOptions,
+) -> SearchResponse {
+    let total: usize = responses.iter().map(|res| res.total).sum();
+    let facets = opts
+        .facets
+        .then(|| merge_facets(responses.iter().filter_map(|res| res.facets.as_ref())));
+
+    let mut ranked: Vec<_> = responses
+        .into_iter()
+        .enumerate()
+        .flat_map(|(user, res)| {
+            res.hits
+                .into_iter()
+                .enumerate()
+                .map(move |(rank, repo)| (rank, user, repo))
+        })
+        .collect();
+    ranked.sort_by(|(rank_a, _, a), (rank_b, _, b)| match opts.sort {
+        Some(Sort::StarredAtDesc) => b.starred_at.cmp(&a.starred_at),
+        Some(Sort::StarredAtAsc) => a.starred_at.cmp(&b.starred_at),
+        // Listings are ordered by the time of the last update, while the
+        // ranks of different users' searches are interleaved.
+        None if listing => b.updated_at.cmp(&a.updated_at),
+        None => rank_a.cmp(rank_b),
+    });
+
+    let mut hits: Vec<(Repository, Vec<usize>)> = vec![];
+    let mut positions: HashMap<u32, usize> = HashMap::new();
+    for (_, user, repo) in ranked {
+        match positions.entry(repo.id) {
+            Entry::Occupied(entry) => hits[*entry.get()].1.push(user),
+            Entry::Vacant(entry) => {
+                entry.insert(hits.len());
+                hits.push((repo, vec![user]));
+            }
+        }
+    }
+    let duplicates: usize = hits.iter().map(|(_, users)| users.len() - 1).sum();
+
+    let hits = hits
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(mut repo, mut starred_by)| {
+            starred_by.sort_unstable();
+            repo.starred_by = starred_by
+                .into_iter()
+                .map(|user| names[user].to_string())
+                .collect();
+            repo
+        })
+        .collect();
+
+    SearchResponse {
+        hits,
+        total: total - duplicates,
+        offset,
+        limit,
+        facets,
+    }
+}
+
+/// Sums the counts of the given facet distributions.
+fn merge_facets<'a>(facets: impl Iterator<Item = &'a Facets>) -> Facets {
+    let mut merged: [HashMap<String, usize>; 5] = Default::default();
+    for facets in facets {
+        let groups = [
+            &facets.language,
+            &facets.topics,
+            &facets.license,
+            &facets.owner,
+            &facets.lists,
+        ];
+        for (counts, group) in merged.iter_mut().zip(groups) {
+            for FacetCount { value, count } in group {
+                *counts.entry(value.clone()).or_default() += count;
+            }
+        }
+    }
+
+    let [language, topics, license, owner, lists] = merged.map(top_facet_values);
+    Facets {
+        language,
+        topics,
+        license,
+        owner,
+        lists,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const USERS: [&str; 2] = ["alice", "bob"];
+
+    /// Returns a response of the given total holding the repositories with
+    /// the given IDs, which have been starred and updated on the day of
+    /// their ID.
+    fn response(ids: &[u32], total: usize) -> SearchResponse {
+        let hits = ids
+            .iter()
+            .map(|&id| {
+                let mut repo = testing::repository(id, &format!("owner/repo{id}"), id);
+                repo.updated_at = repo.starred_at.unwrap();
+                repo
+            })
+            .collect();
+        SearchResponse {
+            hits,
+            total,
+            offset: 0,
+            limit: 10,
+            facets: None,
+        }
+    }
+
+    fn merge(responses: Vec<SearchResponse>, limit: usize, offset: usize) -> Vec<(u32, String)> {
+        merge_sorted(responses, None, limit, offset)
+    }
+
+    fn merge_sorted(
+        responses: Vec<SearchResponse>,
+        sort: Option<Sort>,
+        limit: usize,
+        offset: usize,
+    ) -> Vec<(u32, String)> {
+        let opts = SearchOptions {
+            sort,
+            ..Default::default()
+        };
+        merge_responses(&USERS, responses, false, limit, offset, &opts)
+            .hits
+            .into_iter()
+            .map(|repo| (repo.id, repo.starred_by.join(",")))
+            .collect()
+    }
+
+    #[test]
+    fn interleaves_ranks_of_users() {
+        let responses = vec![response(&[1, 2], 2), response(&[3, 4], 2)];
+        let hits = merge(responses, 10, 0);
+        let ids: Vec<_> = hits.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [1, 3, 2, 4]);
+        assert_eq!(hits[1].1, "bob");
+    }
+
+    #[test]
+    fn deduplicates_repositories_starred_by_several_users() {
+        let responses = vec![response(&[1, 2], 5), response(&[2, 3], 4)];
+        let opts = SearchOptions::default();
+        let res = merge_responses(&USERS, responses, false, 10, 0, &opts);
+
+        let hits: Vec<_> = res
+            .hits
+            .iter()
+            .map(|repo| (repo.id, repo.starred_by.join(",")))
+            .collect();
+        assert_eq!(
+            hits,
+            [
+                (1, "alice".into()),
+                (2, "alice,bob".into()),
+                (3, "bob".into())
+            ]
+        );
+        assert_eq!(res.total, 8);
+    }
+
+    #[test]
+    fn merges_by_sort_order() {
+        let responses = || vec![response(&[5, 1], 2), response(&[4, 2], 2)];
+        let ids = |hits: Vec<(u32, String)>| hits.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+
+        let hits = merge_sorted(responses(), Some(Sort::StarredAtDesc), 10, 0);
+        assert_eq!(ids(hits), [5, 4, 2, 1]);
+        let hits = merge_sorted(responses(), Some(Sort::StarredAtAsc), 10, 0);
+        assert_eq!(ids(hits), [1, 2, 4, 5]);
+
+        // Listings are ordered by the last update.
+        let opts = SearchOptions::default();
+        let res = merge_responses(&USERS, responses(), true, 10, 0, &opts);
+        let hits: Vec<_> = res.hits.iter().map(|repo| repo.id).collect();
+        assert_eq!(hits, [5, 4, 2, 1]);
+    }
+
+    #[test]
+    fn pages_merged_hits() {
+        let responses = vec![response(&[1, 2, 3], 3), response(&[1, 4, 5], 3)];
+        let hits = merge(responses, 2, 2);
+        assert_eq!(hits, [(4, "bob".into()), (3, "alice".into())]);
+    }
+
+    #[test]
+    fn sums_facet_counts() {
+        let count = |value: &str, count| FacetCount {
+            value: value.into(),
+            count,
+        };
+        let alice = Facets {
+            language: vec![count("Rust", 3), count("Go", 1)],
+            topics: vec![count("cli", 2)],
+            ..Default::default()
+        };
+        let bob = Facets {
+            language: vec![count("Go", 4), count("C", 1)],
+            ..Default::default()
+        };
+
+        let merged = merge_facets([&alice, &bob].into_iter());
+        let language: Vec<_> = merged
+            .language
+            .iter()
+            .map(|c| (c.value.as_str(), c.count))
+            .collect();
+        assert_eq!(language, [("Go", 5), ("Rust", 3), ("C", 1)]);
+        assert_eq!(merged.topics.len(), 1);
+        assert_eq!(merged.topics[0].count, 2);
+        assert!(merged.owner.is_empty());
+
+        let mut responses = vec![response(&[1], 1), response(&[2], 1)];
+        responses[0].facets = Some(alice);
+        responses[1].facets = Some(bob);
+        let opts = SearchOptions {
+            facets: true,
+            ..Default::default()
+        };
+        let res = merge_responses(&USERS, responses, false, 10, 0, &opts);
+        assert_eq!(res.facets.unwrap().language[0].count, 5);
+    }
+}
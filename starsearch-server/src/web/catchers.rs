@@ -1,14 +1,28 @@
 use super::models::Error;
 use rocket::http::Status;
+use rocket::response::Redirect;
 use rocket::serde::json::Json;
 use rocket::Request;
 
 #[catch(default)]
 pub fn default_catcher(status: Status, _: &Request) -> (Status, Json<Error>) {
-    (
-        status,
-        Json(Error {
-            message: status.reason_lossy().to_string(),
-        }),
-    )
+    let reason = status.reason_lossy();
+    let code = match status.code {
+        400 => "bad_request",
+        401 => "unauthorized",
+        403 => "forbidden",
+        404 => "not_found",
+        422 => "unprocessable_entity",
+        429 => "too_many_requests",
+        503 => "backend_unavailable",
+        500..=599 => "internal_error",
+        _ => "error",
+    };
+    (status, Json(Error::new(code, reason)))
+}
+
+/// Sends users who are not logged in to the login.
+#[catch(401)]
+pub fn login_redirect() -> Redirect {
+    Redirect::to("/login")
 }
@@ -0,0 +1,178 @@
+use super::guards::{ReadAccess, Viewer};
+use super::models::{Error, FeedEntryViewModel, SearchParams};
+use super::readme;
+use crate::users::{User, Users};
+use chrono::{DateTime, Utc};
+use rocket::http::uri::{Host, Origin};
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket_dyn_templates::{context, Template};
+use starsearch_sdk::models::{Repository, Sort};
+
+/// Maximum number of characters of the README excerpts of feed entries.
+const EXCERPT_LENGTH: usize = 300;
+
+#[derive(Clone, Copy)]
+enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+impl FeedFormat {
+    fn template(self) -> &'static str {
+        match self {
+            Self::Atom => "feed-atom",
+            Self::Rss => "feed-rss",
+        }
+    }
+
+    fn content_type(self) -> ContentType {
+        match self {
+            Self::Atom => ContentType::new("application", "atom+xml"),
+            Self::Rss => ContentType::new("application", "rss+xml"),
+        }
+    }
+
+    fn format_date(self, date: &DateTime<Utc>) -> String {
+        match self {
+            Self::Atom => date.to_rfc3339(),
+            Self::Rss => date.to_rfc2822(),
+        }
+    }
+}
+
+/// Lists the most recently starred repositories matching the search given
+/// by the same parameters as the web UI as Atom feed.
+#[get("/feed.atom?<query>&<params..>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn atom(
+    _auth: ReadAccess,
+    users: &State<Users>,
+    viewer: Viewer,
+    user: &User,
+    host: &Host<'_>,
+    uri: &Origin<'_>,
+    query: Option<&str>,
+    params: SearchParams<'_>,
+) -> Result<(ContentType, Template), (Status, Json<Error>)> {
+    feed(
+        FeedFormat::Atom,
+        users,
+        viewer,
+        user,
+        host,
+        uri,
+        query,
+        params,
+    )
+    .await
+}
+
+/// Lists the most recently starred repositories matching the search given
+/// by the same parameters as the web UI as RSS feed.
+#[get("/feed.rss?<query>&<params..>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn rss(
+    _auth: ReadAccess,
+    users: &State<Users>,
+    viewer: Viewer,
+    user: &User,
+    host: &Host<'_>,
+    uri: &Origin<'_>,
+    query: Option<&str>,
+    params: SearchParams<'_>,
+) -> Result<(ContentType, Template), (Status, Json<Error>)> {
+    feed(
+        FeedFormat::Rss,
+        users,
+        viewer,
+        user,
+        host,
+        uri,
+        query,
+        params,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn feed(
+    format: FeedFormat,
+    users: &Users,
+    viewer: Viewer,
+    user: &User,
+    host: &Host<'_>,
+    uri: &Origin<'_>,
+    query: Option<&str>,
+    params: SearchParams<'_>,
+) -> Result<(ContentType, Template), (Status, Json<Error>)> {
+    let (text, mut opts) = params
+        .options(query.unwrap_or_default())
+        .map_err(|err| (Status::BadRequest, Json(Error::invalid_search(err))))?;
+    opts.sort = Some(Sort::StarredAtDesc);
+
+    let res = super::find(
+        users,
+        &viewer,
+        user,
+        query.map(|_| text.as_str()),
+        params.limit(),
+        0,
+        &opts,
+    )
+    .await?;
+
+    let entries: Vec<_> = res
+        .hits
+        .iter()
+        .map(|repo| FeedEntryViewModel {
+            full_name: &repo.full_name,
+            url: &repo.html_url,
+            description: repo.description.as_deref(),
+            language: repo.language.as_deref(),
+            topics: repo.topics.as_deref().unwrap_or_default(),
+            readme_excerpt: readme::excerpt(repo, EXCERPT_LENGTH),
+            starred_by: &repo.starred_by,
+            date: format.format_date(&entry_date(repo)),
+        })
+        .collect();
+    let updated = res
+        .hits
+        .iter()
+        .map(entry_date)
+        .max()
+        .unwrap_or_else(Utc::now);
+
+    let base_url = super::base_url(users, host);
+    let search = super::search_query(uri);
+    let owner = match opts.team {
+        true => "everyone".to_string(),
+        false => user.name.clone(),
+    };
+
+    let template = Template::render(
+        format.template(),
+        context! {
+            title: match query.filter(|q| !q.is_empty()) {
+                Some(query) => format!("Stars of {owner} matching \"{query}\""),
+                None => format!("Stars of {owner}"),
+            },
+            url: format!("{base_url}/?{search}"),
+            feed_url: format!("{base_url}{}?{search}", uri.path()),
+            updated: format.format_date(&updated),
+            user: &user.name,
+            entries,
+        },
+    );
+
+    Ok((format.content_type(), template))
+}
+
+/// Returns the time the repository has been starred or, if unknown,
+/// scraped.
+fn entry_date(repo: &Repository) -> DateTime<Utc> {
+    repo.starred_at
+        .or(repo.scraped_at)
+        .unwrap_or(repo.updated_at)
+}
@@ -0,0 +1,190 @@
+use crate::oauth::OAuth;
+use crate::tokens::{ApiTokens, Scope};
+use crate::users::{User, Users};
+use rocket::http::{Cookie, SameSite, Status};
+use rocket::outcome::try_outcome;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, State};
+use std::sync::Arc;
+
+/// Name of the private cookie holding the login of the logged in user.
+pub const SESSION_COOKIE: &str = "session";
+
+/// Name of the private cookie holding the API token a browser passed via the
+/// `token` query parameter, so that it is sent along with further requests.
+const TOKEN_COOKIE: &str = "api_token";
+
+/// Query parameter through which browsers and feed readers pass API tokens.
+/// It is dropped from all rendered URLs.
+pub const TOKEN_PARAM: &str = "token";
+
+/// The user logged in via GitHub OAuth, or `None` if login is disabled.
+/// When login is enabled, requests without a valid session fail with 401
+/// unless they carry a valid API token, which grants the access of visitors
+/// who are not logged in.
+pub struct Viewer(pub Option<String>);
+
+impl Viewer {
+    pub fn login(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Viewer {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if req.rocket().state::<OAuth>().is_none() {
+            return Outcome::Success(Viewer(None));
+        }
+
+        let users = try_outcome!(req.guard::<&State<Users>>().await);
+        let login = req
+            .cookies()
+            .get_private(SESSION_COOKIE)
+            .and_then(|cookie| users.account(cookie.value()))
+            .map(|user| user.name.clone());
+
+        match login {
+            Some(login) => Outcome::Success(Viewer(Some(login))),
+            None if token_scope(req).is_some() => Outcome::Success(Viewer(None)),
+            None => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Grants read access to the API, the web UI and the feeds. If API tokens
+/// are configured, requests must carry one of any scope or come from a user
+/// logged in via GitHub.
+pub struct ReadAccess;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReadAccess {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(req, Scope::Read).await.map(|_| ReadAccess)
+    }
+}
+
+/// Grants access to API endpoints which modify data or trigger scrapes. If
+/// API tokens are configured, requests must carry an admin token or come
+/// from a user logged in via GitHub who accesses their own stars.
+pub struct AdminAccess;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAccess {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(req, Scope::Admin).await.map(|_| AdminAccess)
+    }
+}
+
+async fn authorize(req: &Request<'_>, scope: Scope) -> Outcome<(), ()> {
+    let Some(tokens) = req.rocket().state::<ApiTokens>() else {
+        return Outcome::Success(());
+    };
+
+    if let Some(token) = bearer_token(req) {
+        return check_scope(tokens.scope(token), scope);
+    }
+
+    // The web UI of users logged in via GitHub accesses the API with their
+    // session instead of a token.
+    if let Outcome::Success(Viewer(Some(login))) = req.guard::<Viewer>().await {
+        let user = try_outcome!(req.guard::<&User>().await);
+        return check_scope(Some(session_scope(&login, user)), scope);
+    }
+
+    let outcome = check_scope(browser_token_scope(req), scope);
+    if outcome.is_success() {
+        remember_token(req);
+    }
+    outcome
+}
+
+fn check_scope(granted: Option<Scope>, required: Scope) -> Outcome<(), ()> {
+    match granted {
+        Some(granted) if granted >= required => Outcome::Success(()),
+        Some(_) => Outcome::Error((Status::Forbidden, ())),
+        None => Outcome::Error((Status::Unauthorized, ())),
+    }
+}
+
+/// Returns the access a session of the given login grants to the stars of
+/// the given user. Only their own stars may be modified, as the ones of the
+/// configured users are shared with everyone.
+fn session_scope(login: &str, user: &User) -> Scope {
+    match user.private && user.name.eq_ignore_ascii_case(login) {
+        true => Scope::Admin,
+        false => Scope::Read,
+    }
+}
+
+/// Returns the scope of the API token the request carries, or `None` if it
+/// carries none or an unknown one.
+fn token_scope(req: &Request<'_>) -> Option<Scope> {
+    match bearer_token(req) {
+        Some(token) => req.rocket().state::<ApiTokens>()?.scope(token),
+        None => browser_token_scope(req),
+    }
+}
+
+fn bearer_token<'r>(req: &'r Request<'_>) -> Option<&'r str> {
+    req.headers()
+        .get_one("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Returns the scope of the API token passed by browsers and feed readers,
+/// which can not send a bearer token, as `token` query parameter or in the
+/// cookie set by an earlier request. These tokens end up in histories,
+/// bookmarks and cookies, so only read tokens are accepted.
+fn browser_token_scope(req: &Request<'_>) -> Option<Scope> {
+    let tokens = req.rocket().state::<ApiTokens>()?;
+    let token = match query_token(req) {
+        Some(token) => token.to_string(),
+        None => req.cookies().get_private(TOKEN_COOKIE)?.value().to_string(),
+    };
+    tokens.scope(&token).filter(|scope| *scope == Scope::Read)
+}
+
+fn query_token<'r>(req: &'r Request<'_>) -> Option<&'r str> {
+    req.query_value::<&str>(TOKEN_PARAM)
+        .and_then(Result::ok)
+        .filter(|token| !token.is_empty())
+}
+
+/// Stores a valid API token passed as query parameter in a cookie, so that
+/// browsers keep sending it while navigating the web UI.
+fn remember_token(req: &Request<'_>) {
+    if let Some(token) = query_token(req) {
+        req.cookies()
+            .add_private(Cookie::build((TOKEN_COOKIE, token.to_string())).same_site(SameSite::Lax));
+    }
+}
+
+/// Selects the user named by the `user` query parameter or, if it is
+/// missing, the default user of the logged in user. Requests naming an
+/// unknown user or one whose stars are not accessible fail with 404.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r User {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let viewer = try_outcome!(req.guard::<Viewer>().await);
+        let users = try_outcome!(req.guard::<&State<Users>>().await);
+        let name = req
+            .query_value::<&str>("user")
+            .and_then(Result::ok)
+            .filter(|name| !name.is_empty());
+
+        let user: &Option<Arc<User>> = req.local_cache(|| users.get(name, viewer.login()));
+        match user {
+            Some(user) => Outcome::Success(user),
+            None => Outcome::Error((Status::NotFound, ())),
+        }
+    }
+}
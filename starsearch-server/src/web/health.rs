@@ -0,0 +1,56 @@
+use super::models::Readiness;
+use crate::users::Users;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use starsearch_sdk::models::{Job, JobState};
+use std::collections::HashMap;
+
+/// Responds as long as the server is running.
+#[get("/healthz")]
+pub fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Checks whether the server can serve searches, i.e. the search backends
+/// of all users are reachable with their indexes set up, and responds with
+/// 503 otherwise. Failed scrapes are reported as well, but do not affect the
+/// readiness, as the previously scraped stars can still be searched.
+#[get("/readyz")]
+pub async fn readyz(users: &State<Users>) -> (Status, Json<Readiness>) {
+    let mut backend_ok = true;
+    for user in users.all() {
+        if let Err(err) = user.db.check().await {
+            warn!("Search backend of {} is not ready: {err}", user.name);
+            backend_ok = false;
+        }
+    }
+
+    // Jobs are listed most recent first, so the first finished job of each
+    // user is their last scrape.
+    let mut last_scrapes: HashMap<String, Job> = HashMap::new();
+    for job in users.jobs().list() {
+        if job.state.is_finished() {
+            last_scrapes.entry(job.user.clone()).or_insert(job);
+        }
+    }
+    let last_scrape = if last_scrapes.is_empty() {
+        "none"
+    } else if last_scrapes.values().any(|j| j.state == JobState::Failed) {
+        "failed"
+    } else {
+        "succeeded"
+    };
+
+    let readiness = Readiness {
+        ready: backend_ok,
+        search_backend: if backend_ok { "ok" } else { "unavailable" },
+        last_scrape,
+    };
+    let status = match readiness.ready {
+        true => Status::Ok,
+        false => Status::ServiceUnavailable,
+    };
+
+    (status, Json(readiness))
+}
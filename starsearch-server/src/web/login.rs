@@ -0,0 +1,212 @@
+use super::guards::SESSION_COOKIE;
+use crate::oauth::{self, OAuth};
+use crate::users::accounts::Account;
+use crate::users::Users;
+use rocket::http::{Cookie, CookieJar, SameSite, Status};
+use rocket::response::Redirect;
+use rocket::time::Duration;
+use rocket::State;
+
+/// Name of the private cookie holding the state of a pending login.
+const STATE_COOKIE: &str = "oauth_state";
+
+/// Time in which a login has to be completed on GitHub.
+const LOGIN_TIMEOUT: Duration = Duration::minutes(10);
+
+/// Redirects to GitHub to log in.
+#[get("/login")]
+pub fn login(oauth: &State<OAuth>, cookies: &CookieJar<'_>) -> Redirect {
+    let state = oauth::random_state();
+
+    // GitHub redirects back to the callback from another site, so cookies
+    // must not be restricted to same-site requests.
+    cookies.add_private(
+        Cookie::build((STATE_COOKIE, state.clone()))
+            .same_site(SameSite::Lax)
+            .max_age(LOGIN_TIMEOUT),
+    );
+
+    Redirect::to(oauth.authorize_url(&state))
+}
+
+/// Completes the login after the user authorized the app on GitHub and
+/// starts the session. Must match [`oauth::CALLBACK_PATH`].
+#[get("/login/callback?<code>&<state>&<error>")]
+pub async fn callback(
+    oauth: &State<OAuth>,
+    users: &State<Users>,
+    cookies: &CookieJar<'_>,
+    code: Option<&str>,
+    state: Option<&str>,
+    error: Option<&str>,
+) -> Result<Redirect, (Status, String)> {
+    let expected_state = cookies
+        .get_private(STATE_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+    cookies.remove_private(STATE_COOKIE);
+
+    if let Some(error) = error {
+        return Err((Status::Forbidden, format!("Login has been denied: {error}")));
+    }
+    let (Some(code), Some(state)) = (code, state) else {
+        return Err((Status::BadRequest, "Missing code or state.".into()));
+    };
+    if expected_state.as_deref() != Some(state) {
+        return Err((
+            Status::BadRequest,
+            "Login has expired, please try again.".into(),
+        ));
+    }
+
+    let bad_gateway = |err: oauth::errors::OAuthError| (Status::BadGateway, err.to_string());
+    let token = oauth.exchange(code).await.map_err(bad_gateway)?;
+    let login = oauth.login(&token).await.map_err(bad_gateway)?;
+
+    users
+        .sign_in(Account {
+            login: login.clone(),
+            token,
+        })
+        .await
+        .map_err(|err| (Status::InternalServerError, err.to_string()))?;
+
+    cookies.add_private(Cookie::build((SESSION_COOKIE, login)).same_site(SameSite::Lax));
+
+    Ok(Redirect::to("/"))
+}
+
+#[post("/logout")]
+pub fn logout(cookies: &CookieJar<'_>) -> Redirect {
+    cookies.remove_private(SESSION_COOKIE);
+    Redirect::to("/login")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::web::{build, random_secret_key};
+    use rocket::http::uri::Origin;
+    use rocket::local::asynchronous::Client;
+    use rocket::Config;
+    use std::fs;
+    use tempfile::TempDir;
+    use tokio_cron_scheduler::JobScheduler;
+
+    /// Serves the web app with login via a fake GitHub, which grants the
+    /// token `token-<n>` of `alice` for the code `code-<n>`.
+    async fn client(dir: &TempDir) -> Client {
+        // Templates and static files are served from the workspace.
+        std::env::set_current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/..")).unwrap();
+
+        let github = testing::http_server(|head, body| {
+            if head.starts_with("POST /login/oauth/access_token") {
+                assert!(body.contains("client_secret=secret"), "{body}");
+                return match body.split('&').find_map(|p| p.strip_prefix("code=code-")) {
+                    Some(n) => (200, format!(r#"{{"access_token":"token-{n}"}}"#)),
+                    None => (200, r#"{"error":"bad_verification_code"}"#.into()),
+                };
+            }
+            if head.starts_with("GET /user ") && head.contains("Bearer token-") {
+                return (200, r#"{"login":"alice"}"#.into());
+            }
+            (404, r#"{"message":"Not Found"}"#.into())
+        })
+        .await;
+
+        let mut cfg = testing::config(dir.path());
+        cfg.github_url = Some(github.clone());
+        cfg.github_api_url = Some(github);
+        cfg.github_client_id = Some("client".into());
+        cfg.github_client_secret = Some("secret".into());
+
+        let oauth = OAuth::from_config(&cfg).unwrap();
+        let users = Users::new(cfg, None, JobScheduler::new().await.unwrap()).unwrap();
+        let figment = Config::figment().merge((Config::SECRET_KEY, random_secret_key()));
+        Client::tracked(build(users, oauth, None).configure(figment))
+            .await
+            .unwrap()
+    }
+
+    /// Starts a login and returns the state passed to GitHub.
+    async fn start_login(client: &Client) -> String {
+        let res = client.get("/login").dispatch().await;
+        assert_eq!(res.status(), Status::SeeOther);
+        let location = res.headers().get_one("Location").unwrap();
+        let (_, query) = location.split_once('?').unwrap();
+        query
+            .split('&')
+            .find_map(|p| p.strip_prefix("state="))
+            .unwrap()
+            .to_string()
+    }
+
+    async fn callback(client: &Client, code: &str, state: &str) -> Status {
+        let uri = format!("/login/callback?code={code}&state={state}");
+        client
+            .get(Origin::parse_owned(uri).unwrap())
+            .dispatch()
+            .await
+            .status()
+    }
+
+    fn session(client: &Client) -> Option<String> {
+        let cookie = client.cookies().get_private(SESSION_COOKIE)?;
+        Some(cookie.value().to_string())
+    }
+
+    #[rocket::async_test]
+    async fn callback_requires_state_of_login() {
+        let dir = TempDir::new().unwrap();
+        let client = client(&dir).await;
+
+        let state = start_login(&client).await;
+        assert_eq!(
+            callback(&client, "code-1", "forged").await,
+            Status::BadRequest
+        );
+        // The state is only valid once.
+        assert_eq!(
+            callback(&client, "code-1", &state).await,
+            Status::BadRequest
+        );
+        assert_eq!(session(&client), None);
+    }
+
+    #[rocket::async_test]
+    async fn callback_rejects_invalid_code() {
+        let dir = TempDir::new().unwrap();
+        let client = client(&dir).await;
+
+        let state = start_login(&client).await;
+        assert_eq!(callback(&client, "wrong", &state).await, Status::BadGateway);
+        assert_eq!(session(&client), None);
+    }
+
+    #[rocket::async_test]
+    async fn callback_signs_in_and_replaces_token() {
+        let dir = TempDir::new().unwrap();
+        let client = client(&dir).await;
+        let users = client.rocket().state::<Users>().unwrap();
+
+        let state = start_login(&client).await;
+        assert_eq!(callback(&client, "code-1", &state).await, Status::SeeOther);
+        assert_eq!(session(&client).as_deref(), Some("alice"));
+        assert!(users.get(Some("alice"), Some("alice")).is_some());
+
+        let res = client.post("/logout").dispatch().await;
+        assert_eq!(res.status(), Status::SeeOther);
+        assert_eq!(session(&client), None);
+
+        // Logging in again keeps the user and stores the new token.
+        let state = start_login(&client).await;
+        assert_eq!(callback(&client, "code-2", &state).await, Status::SeeOther);
+        assert_eq!(session(&client).as_deref(), Some("alice"));
+        let logins: Vec<_> = users.all().iter().map(|u| u.name.clone()).collect();
+        assert_eq!(logins, ["alice"]);
+
+        let accounts = fs::read_to_string(dir.path().join("accounts.json")).unwrap();
+        assert!(accounts.contains("token-2"), "{accounts}");
+        assert!(!accounts.contains("token-1"), "{accounts}");
+    }
+}
@@ -0,0 +1,113 @@
+use super::guards::ReadAccess;
+use crate::metrics::{self, METRICS};
+use crate::users::Users;
+use chrono::Local;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ContentType;
+use rocket::{Data, Request, Response, State};
+use std::time::Instant;
+
+/// Records the count and latency of all requests by their route.
+pub struct RequestMetrics;
+
+/// Time at which the handling of a request has started.
+struct RequestStart(Instant);
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        req.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let started = req.local_cache(|| RequestStart(Instant::now()));
+        // Routes are recorded by their URI template to keep the number of
+        // distinct label values small.
+        let route = req
+            .route()
+            .map(|route| route.uri.path())
+            .unwrap_or("unmatched");
+
+        METRICS.observe_request(
+            req.method().as_str(),
+            route,
+            res.status().code,
+            started.0.elapsed(),
+        );
+    }
+}
+
+/// Exposes the metrics of the server in the Prometheus text format. Users
+/// who logged in are left out, so that their logins are not disclosed.
+#[get("/metrics")]
+pub async fn export(_auth: ReadAccess, users: &State<Users>) -> (ContentType, String) {
+    let users = users.all();
+    let is_private = |name: &str| {
+        users
+            .iter()
+            .any(|user| user.private && user.name.eq_ignore_ascii_case(name))
+    };
+
+    let mut out = String::new();
+    METRICS.render(&mut out, |user| !is_private(user));
+
+    let mut documents = vec![];
+    let mut index_ages = vec![];
+    for user in users.iter().filter(|user| !user.private) {
+        let info = match user.db.get_info().await {
+            Ok(info) => info,
+            Err(err) => {
+                error!("failed getting index info of {}: {err}", user.name);
+                continue;
+            }
+        };
+
+        documents.push((user.name.clone(), info.index_count));
+        let dates = [
+            ("fast", info.index_dates.last_fast_index),
+            ("full", info.index_dates.last_full_index),
+        ];
+        for (kind, date) in dates {
+            if let Some(date) = date {
+                let age = (Local::now() - date).num_milliseconds() as f64 / 1000.0;
+                index_ages.push((user.name.clone(), kind, age));
+            }
+        }
+    }
+
+    metrics::write_header(
+        &mut out,
+        "starsearch_index_documents",
+        "gauge",
+        "Number of repositories in the index.",
+    );
+    for (user, count) in documents {
+        metrics::write_sample(
+            &mut out,
+            "starsearch_index_documents",
+            &[("user", &user)],
+            count,
+        );
+    }
+
+    metrics::write_header(
+        &mut out,
+        "starsearch_index_age_seconds",
+        "gauge",
+        "Seconds since the last successful fast and full index.",
+    );
+    for (user, kind, age) in index_ages {
+        let labels = [("user", user.as_str()), ("kind", kind)];
+        metrics::write_sample(&mut out, "starsearch_index_age_seconds", &labels, age);
+    }
+
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    (content_type, out)
+}
@@ -1,86 +1,905 @@
 mod catchers;
+mod feed;
+mod guards;
+mod health;
+mod login;
+mod metrics;
 mod models;
 mod ratelimit;
+mod readme;
 
-use self::models::{Error, RepositoryViewModel};
-use crate::db::Database;
-use crate::scraper::Scraper;
+use self::guards::{AdminAccess, ReadAccess, Viewer, TOKEN_PARAM};
+use self::models::{
+    classify, Error, FacetViewModel, PaginationViewModel, RepositoryDetailViewModel,
+    RepositoryViewModel, SearchParams, Suggestions, MAX_LIMIT,
+};
+use crate::db;
+use crate::db::errors::DatabaseError;
+use crate::oauth::OAuth;
+use crate::tokens::ApiTokens;
+use crate::users::{User, Users};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use rand::RngCore;
 use rocket::fs::FileServer;
-use rocket::http::Status;
+use rocket::http::uri::{Host, Origin};
+use rocket::http::{ContentType, RawStr, Status, StatusClass};
+use rocket::response::stream::{Event, EventStream};
 use rocket::serde::json::Json;
-use rocket::{Config, State};
+use rocket::{Build, Config, Rocket, State};
 use rocket_dyn_templates::{context, Template};
 use rocket_governor::RocketGovernor;
-use starsearch_sdk::models::{Repository, ServerInfo};
-use std::sync::Arc;
+use starsearch_sdk::models::{
+    Annotations, Job, JobKind, Repository, SearchOptions, SearchResponse, ServerInfo,
+};
 
-#[get("/?<query>&<limit>&<language>")]
+/// Number of similar repositories returned by default.
+const DEFAULT_SIMILAR_LIMIT: usize = 10;
+
+/// Number of search suggestions returned by default.
+const DEFAULT_SUGGEST_LIMIT: usize = 8;
+
+#[get("/?<query>&<params..>")]
 async fn index(
-    db: &State<Arc<Database>>,
+    _auth: ReadAccess,
+    users: &State<Users>,
+    viewer: Viewer,
+    user: &User,
+    uri: &Origin<'_>,
     query: Option<&str>,
-    limit: Option<usize>,
-    language: Option<&str>,
-) -> Template {
-    let res = if let Some(query) = query {
-        db.search(query, limit.unwrap_or(30), language).await
+    params: SearchParams<'_>,
+) -> Result<Template, (Status, Template)> {
+    // Rejected searches are shown along with the search form to be fixed.
+    let invalid_search = |message: String| {
+        let template = Template::render(
+            "index",
+            context! {
+                query: query.unwrap_or_default(),
+                language_filter: params.language,
+                semantic_search: user.db.semantic_search(),
+                mode: params.mode.value().map(|m| m.0).unwrap_or_default(),
+                users: user_choices(users, &viewer),
+                user: &user.name,
+                team: params.team.value().copied().unwrap_or_default(),
+                viewer: viewer.login(),
+                error: message,
+            },
+        );
+        (Status::BadRequest, template)
+    };
+
+    let (text, mut opts) = params
+        .options(query.unwrap_or_default())
+        .map_err(|err| invalid_search(err.to_string()))?;
+    let (limit, offset) = (params.limit(), params.offset());
+    opts.facets = true;
+
+    let res = find(
+        users,
+        &viewer,
+        user,
+        query.map(|_| text.as_str()),
+        limit,
+        offset,
+        &opts,
+    )
+    .await
+    .map_err(|err| match classify(&err) {
+        (status, _) if status == Status::BadRequest => invalid_search(err.to_string()),
+        _ => error_page(&err, &viewer, uri),
+    })?;
+
+    let pagination = PaginationViewModel::new(&res, |offset| with_offset(uri, offset));
+
+    let facets = res.facets.as_ref().map(|facets| {
+        FacetViewModel::new(facets, &opts.filter, |param, value, active| {
+            with_facet(uri, param, value, active)
+        })
+    });
+
+    let default_user = users.get(None, viewer.login()).map(|u| u.name.clone());
+    let results: Vec<_> = res
+        .hits
+        .iter()
+        .map(|repo| {
+            // Hits of team-wide searches are shown from the stars of the
+            // first user who starred them.
+            let user = match opts.team {
+                true => repo
+                    .starred_by
+                    .first()
+                    .map(String::as_str)
+                    .filter(|&name| Some(name) != default_user.as_deref()),
+                false => user_param(users, &viewer, user),
+            };
+            RepositoryViewModel::new(repo, user)
+        })
+        .collect();
+
+    Ok(Template::render(
+        "index",
+        context! {
+            query: query.unwrap_or_default(),
+            language_filter: params.language,
+            results,
+            pagination,
+            facets,
+            semantic_search: user.db.semantic_search(),
+            mode: opts.mode,
+            users: user_choices(users, &viewer),
+            user: &user.name,
+            team: opts.team,
+            viewer: viewer.login(),
+            atom_feed: feed_url(uri, "atom"),
+            rss_feed: feed_url(uri, "rss"),
+        },
+    ))
+}
+
+/// Renders the page explaining why a page could not be shown because of the
+/// given error, with a hint to retry if it may be temporary.
+fn error_page(err: &DatabaseError, viewer: &Viewer, uri: &Origin<'_>) -> (Status, Template) {
+    let (status, code) = classify(err);
+    let uri = without_token(uri);
+    if status.class() == StatusClass::ServerError {
+        error!("Failed to render {uri}: {err}");
+    }
+
+    let template = Template::render(
+        "error",
+        context! {
+            status: status.code,
+            reason: status.reason_lossy(),
+            code,
+            message: err.to_string(),
+            unavailable: status == Status::ServiceUnavailable,
+            retry: status.class() == StatusClass::ServerError,
+            retry_url: uri,
+            viewer: viewer.login(),
+        },
+    );
+    (status, template)
+}
+
+/// Describes the search for browsers to add it as search engine.
+#[get("/opensearch.xml")]
+fn opensearch(users: &State<Users>, host: &Host<'_>) -> (ContentType, Template) {
+    let content_type = ContentType::new("application", "opensearchdescription+xml");
+    let template = Template::render(
+        "opensearch",
+        context! {
+            base_url: base_url(users, host),
+        },
+    );
+    (content_type, template)
+}
+
+/// Searches the stars of the given user or, in team mode, of all users
+/// visible to the viewer. Lists them instead if no query is given.
+async fn find(
+    users: &Users,
+    viewer: &Viewer,
+    user: &User,
+    query: Option<&str>,
+    limit: usize,
+    offset: usize,
+    opts: &SearchOptions,
+) -> db::errors::Result<SearchResponse> {
+    if opts.team {
+        users
+            .search(viewer.login(), query, limit, offset, opts)
+            .await
+    } else if let Some(query) = query {
+        user.db.search(query, limit, offset, opts).await
     } else {
-        db.list(limit.unwrap_or(30), language).await
+        user.db.list(limit, offset, opts).await
     }
-    .unwrap();
+}
 
-    let res: Vec<_> = res.iter().map(RepositoryViewModel::from).collect();
+#[get("/repositories/<id>")]
+async fn repository_page(
+    _auth: ReadAccess,
+    users: &State<Users>,
+    viewer: Viewer,
+    user: &User,
+    uri: &Origin<'_>,
+    id: u32,
+) -> Result<Option<Template>, (Status, Template)> {
+    let repos = user.db.get_repos(&[id]).await;
+    let Some(repo) = repos.map_err(|err| error_page(&err, &viewer, uri))?.pop() else {
+        return Ok(None);
+    };
+    let user = user_param(users, &viewer, user);
 
-    Template::render(
+    Ok(Some(Template::render(
+        "repository",
+        context! {
+            repository: RepositoryDetailViewModel::new(&repo, user),
+            user,
+            viewer: viewer.login(),
+        },
+    )))
+}
+
+#[get("/repositories/<id>/similar")]
+async fn similar_page(
+    _auth: ReadAccess,
+    users: &State<Users>,
+    viewer: Viewer,
+    user: &User,
+    uri: &Origin<'_>,
+    id: u32,
+) -> Result<Option<Template>, (Status, Template)> {
+    let repos = user.db.get_repos(&[id]).await;
+    let Some(repo) = repos.map_err(|err| error_page(&err, &viewer, uri))?.pop() else {
+        return Ok(None);
+    };
+    let similar = user.db.similar(id, DEFAULT_SIMILAR_LIMIT).await;
+    let Some(similar) = similar.map_err(|err| error_page(&err, &viewer, uri))? else {
+        return Ok(None);
+    };
+
+    let results: Vec<_> = similar
+        .iter()
+        .map(|repo| RepositoryViewModel::new(repo, user_param(users, &viewer, user)))
+        .collect();
+
+    Ok(Some(Template::render(
         "index",
         context! {
-            query: query.unwrap_or_default(),
-            language_filter: language,
-            results: res,
+            similar_to: repo.full_name,
+            results,
+            semantic_search: user.db.semantic_search(),
+            users: user_choices(users, &viewer),
+            user: &user.name,
+            viewer: viewer.login(),
         },
-    )
+    )))
 }
 
-#[get("/search?<query>&<limit>&<language>")]
+#[get("/search?<query>&<params..>")]
 async fn search(
-    db: &State<Arc<Database>>,
+    _auth: ReadAccess,
+    users: &State<Users>,
+    viewer: Viewer,
+    user: &User,
     query: &str,
+    params: SearchParams<'_>,
+) -> Result<Json<SearchResponse>, (Status, Json<Error>)> {
+    let (text, opts) = params
+        .options(query)
+        .map_err(|err| (Status::BadRequest, Json(Error::invalid_search(err))))?;
+
+    let res = if opts.team {
+        users
+            .search(
+                viewer.login(),
+                Some(&text),
+                params.limit(),
+                params.offset(),
+                &opts,
+            )
+            .await?
+    } else {
+        user.db
+            .search(&text, params.limit(), params.offset(), &opts)
+            .await?
+    };
+
+    Ok(Json(res))
+}
+
+/// Suggests the names of the top hits of the given query for browsers to
+/// complete it.
+#[get("/suggest?<query>&<params..>")]
+async fn suggest(
+    _auth: ReadAccess,
+    users: &State<Users>,
+    viewer: Viewer,
+    user: &User,
+    query: &str,
+    params: SearchParams<'_>,
+) -> Result<Json<Suggestions>, (Status, Json<Error>)> {
+    let (text, opts) = params
+        .options(query)
+        .map_err(|err| (Status::BadRequest, Json(Error::invalid_search(err))))?;
+    if text.trim().is_empty() {
+        return Ok(Json(Suggestions(query.into(), vec![], vec![], vec![])));
+    }
+
+    let limit = params
+        .limit
+        .value()
+        .copied()
+        .unwrap_or(DEFAULT_SUGGEST_LIMIT)
+        .min(MAX_LIMIT);
+    let res = find(users, &viewer, user, Some(&text), limit, 0, &opts).await?;
+
+    let mut suggestions = Suggestions(query.into(), vec![], vec![], vec![]);
+    for repo in res.hits {
+        suggestions.1.push(repo.full_name);
+        suggestions.2.push(repo.description.unwrap_or_default());
+        suggestions.3.push(repo.html_url);
+    }
+    Ok(Json(suggestions))
+}
+
+#[get("/repositories/<id>")]
+async fn repository(
+    _auth: ReadAccess,
+    user: &User,
+    id: u32,
+) -> Result<Option<Json<Repository>>, (Status, Json<Error>)> {
+    let repo = user.db.get_repos(&[id]).await?.pop();
+    Ok(repo.map(Json))
+}
+
+#[get("/repositories/by-name/<owner>/<name>")]
+async fn repository_by_name(
+    _auth: ReadAccess,
+    user: &User,
+    owner: &str,
+    name: &str,
+) -> Result<Option<Json<Repository>>, (Status, Json<Error>)> {
+    let repo = user.db.get_repo_by_name(owner, name).await?;
+    Ok(repo.map(Json))
+}
+
+#[put("/repositories/<id>/annotations", data = "<annotations>")]
+async fn annotations(
+    _auth: AdminAccess,
+    user: &User,
+    id: u32,
+    annotations: Json<Annotations>,
+) -> Result<Option<Json<Annotations>>, (Status, Json<Error>)> {
+    let repo = user
+        .db
+        .set_annotations(id, annotations.into_inner())
+        .await?;
+    Ok(repo.map(|r| Json(r.annotations.unwrap_or_default())))
+}
+
+#[get("/repositories/<id>/similar?<limit>")]
+async fn similar(
+    _auth: ReadAccess,
+    user: &User,
+    id: u32,
     limit: Option<usize>,
-    language: Option<&str>,
-) -> Json<Vec<Repository>> {
-    let res = db
-        .search(query, limit.unwrap_or(30), language)
-        .await
-        .unwrap();
+) -> Result<Option<Json<Vec<Repository>>>, (Status, Json<Error>)> {
+    let limit = limit.unwrap_or(DEFAULT_SIMILAR_LIMIT).min(MAX_LIMIT);
+    let similar = user.db.similar(id, limit).await?;
+    Ok(similar.map(Json))
+}
+
+/// Returns the given URI with its paging parameters replaced by the given
+/// offset.
+fn with_offset(uri: &Origin<'_>, offset: usize) -> String {
+    if offset == 0 {
+        return with_query(uri, |_, _| true, &[]);
+    }
+
+    with_query(uri, |_, _| true, &[("offset", &offset.to_string())])
+}
+
+/// Returns the absolute URL of the web app, which feeds and the OpenSearch
+/// description require. The public URL is preferred over the requested host,
+/// behind which a proxy may hide the scheme.
+fn base_url(users: &Users, host: &Host<'_>) -> String {
+    match &users.config().public_url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => format!("http://{host}"),
+    }
+}
+
+/// Returns the URL of the feed of the given format listing the results of the
+/// search of the given URI.
+fn feed_url(uri: &Origin<'_>, format: &str) -> String {
+    format!("/feed.{format}?{}", search_query(uri))
+}
+
+/// Returns the query of the search of the given URI, without paging.
+fn search_query(uri: &Origin<'_>) -> String {
+    let search = with_query(uri, |_, _| true, &[]);
+    let (_, query) = search.split_once('?').unwrap_or_default();
+    query.to_string()
+}
+
+/// Returns the given URI with the filter of the given facet value added or,
+/// if it is active, removed. Paging starts over at the first page.
+fn with_facet(uri: &Origin<'_>, param: &str, value: &str, active: bool) -> String {
+    // Topics and lists are the only facets of which several values can be
+    // required.
+    let multiple = param == "topic" || param == "list";
+    let keep = |k: &str, v: &str| k != param || (multiple && !v.eq_ignore_ascii_case(value));
+    let append: &[_] = if active { &[] } else { &[(param, value)] };
+
+    with_query(uri, keep, append)
+}
+
+/// Returns the given URI with its query rebuilt from the parameters for which
+/// `keep` returns true and the appended ones. Paging parameters are always
+/// dropped.
+fn with_query(
+    uri: &Origin<'_>,
+    keep: impl Fn(&str, &str) -> bool,
+    append: &[(&str, &str)],
+) -> String {
+    rebuild_query(
+        uri,
+        |k, v| k != "offset" && k != "page" && keep(k, v),
+        append,
+    )
+}
+
+/// Returns the given URI without the API token passed by the browser, which
+/// must not end up in rendered links.
+fn without_token(uri: &Origin<'_>) -> String {
+    rebuild_query(uri, |_, _| true, &[])
+}
 
-    Json(res)
+fn rebuild_query(
+    uri: &Origin<'_>,
+    keep: impl Fn(&str, &str) -> bool,
+    append: &[(&str, &str)],
+) -> String {
+    let segments: Vec<_> = uri
+        .query()
+        .into_iter()
+        .flat_map(|q| q.segments())
+        .filter(|(k, v)| *k != TOKEN_PARAM && keep(k, v))
+        .chain(append.iter().copied())
+        .map(|(k, v)| {
+            format!(
+                "{}={}",
+                RawStr::new(k).percent_encode(),
+                RawStr::new(v).percent_encode()
+            )
+        })
+        .collect();
+
+    format!("{}?{}", uri.path(), segments.join("&"))
 }
 
-#[post("/refresh")]
-async fn refresh(
+#[post("/refresh?<full>")]
+fn refresh(
+    _auth: AdminAccess,
     _limit: RocketGovernor<'_, ratelimit::Refresh>,
-    scraper: &State<Arc<Scraper>>,
-) -> Result<Status, (Status, Json<Error>)> {
-    scraper.index(true).await?;
-    Ok(Status::Ok)
+    user: &User,
+    full: Option<bool>,
+) -> (Status, Json<Job>) {
+    let kind = if full.unwrap_or_default() { JobKind::Full } else { JobKind::Fast };
+
+    let job = user.scraper.start(kind);
+    (Status::Accepted, Json(job))
+}
+
+#[get("/jobs")]
+fn jobs(_auth: ReadAccess, users: &State<Users>, viewer: Viewer) -> Json<Vec<Job>> {
+    let jobs = users
+        .jobs()
+        .list()
+        .into_iter()
+        .filter(|job| is_job_visible(users, &viewer, job))
+        .collect();
+    Json(jobs)
+}
+
+#[get("/jobs/<id>")]
+fn job(_auth: ReadAccess, users: &State<Users>, viewer: Viewer, id: u64) -> Option<Json<Job>> {
+    let job = users.jobs().get(id)?.get();
+    is_job_visible(users, &viewer, &job).then_some(Json(job))
+}
+
+/// Streams the state of the given job on every update until it has finished.
+#[get("/jobs/<id>/events")]
+fn job_events(
+    _auth: ReadAccess,
+    users: &State<Users>,
+    viewer: Viewer,
+    id: u64,
+) -> Option<EventStream![]> {
+    let job = users.jobs().get(id)?;
+    if !is_job_visible(users, &viewer, &job.get()) {
+        return None;
+    }
+    let mut updates = job.subscribe();
+
+    Some(EventStream! {
+        loop {
+            let job = updates.borrow_and_update().clone();
+            let finished = job.state.is_finished();
+            yield Event::json(&job);
+
+            if finished || updates.changed().await.is_err() {
+                break;
+            }
+        }
+    })
 }
 
 #[get("/serverinfo")]
-async fn server_info(db: &State<Arc<Database>>) -> Result<Json<ServerInfo>, (Status, Json<Error>)> {
-    let server_info = db.get_info().await?;
+async fn server_info(
+    _auth: ReadAccess,
+    users: &State<Users>,
+    viewer: Viewer,
+    user: &User,
+) -> Result<Json<ServerInfo>, (Status, Json<Error>)> {
+    let server_info = ServerInfo {
+        users: user_names(users, &viewer),
+        ..user.db.get_info().await?
+    };
     Ok(Json(server_info))
 }
 
-pub async fn run(db: Arc<Database>, scraper: Arc<Scraper>) -> Result<(), rocket::Error> {
-    rocket::build()
-        .manage(db)
-        .manage(scraper)
-        .mount("/", routes![index])
-        .mount("/api", routes![search, refresh, server_info])
-        .mount("/static", FileServer::from("static"))
-        .register("/api", catchers![catchers::default_catcher])
-        .configure(Config::figment())
-        .attach(Template::fairing())
+/// Returns the names of the users whose stars are accessible to the logged
+/// in user, the default one first.
+fn user_names(users: &Users, viewer: &Viewer) -> Vec<String> {
+    users
+        .visible(viewer.login())
+        .iter()
+        .map(|user| user.name.clone())
+        .collect()
+}
+
+/// Returns the names of the users to choose from in the web UI, which is
+/// only offered if there are several users.
+fn user_choices(users: &Users, viewer: &Viewer) -> Vec<String> {
+    let names = user_names(users, viewer);
+    if names.len() > 1 {
+        names
+    } else {
+        vec![]
+    }
+}
+
+/// Returns the value of the `user` query parameter selecting the given user
+/// in links, which is omitted for the default user.
+fn user_param<'a>(users: &Users, viewer: &Viewer, user: &'a User) -> Option<&'a str> {
+    let default_user = users.get(None, viewer.login());
+    let is_default = default_user.is_some_and(|default| std::ptr::eq(&*default, user));
+    (!is_default).then_some(user.name.as_str())
+}
+
+/// Whether the logged in user may see the given job, which is the case if
+/// they can access the stars it scrapes.
+fn is_job_visible(users: &Users, viewer: &Viewer, job: &Job) -> bool {
+    users
+        .visible(viewer.login())
+        .iter()
+        .any(|user| user.name == job.user)
+}
+
+pub async fn run(
+    users: Users,
+    oauth: Option<OAuth>,
+    tokens: Option<ApiTokens>,
+) -> Result<(), rocket::Error> {
+    let mut figment = Config::figment();
+    if !figment.contains(Config::SECRET_KEY) {
+        if oauth.is_some() || tokens.is_some() {
+            warn!("No secret key is configured, so sessions end on restart");
+        }
+        figment = figment.merge((Config::SECRET_KEY, random_secret_key()));
+    }
+
+    build(users, oauth, tokens)
+        .configure(figment)
         .launch()
         .await?;
     Ok(())
 }
+
+/// Assembles the web app serving the stars of the given users.
+fn build(users: Users, oauth: Option<OAuth>, tokens: Option<ApiTokens>) -> Rocket<Build> {
+    let mut rocket = rocket::build()
+        .manage(users)
+        .mount(
+            "/",
+            routes![
+                index,
+                repository_page,
+                similar_page,
+                opensearch,
+                feed::atom,
+                feed::rss,
+                metrics::export,
+                health::healthz,
+                health::readyz
+            ],
+        )
+        .mount(
+            "/api",
+            routes![
+                search,
+                suggest,
+                repository,
+                repository_by_name,
+                annotations,
+                similar,
+                refresh,
+                jobs,
+                job,
+                job_events,
+                server_info
+            ],
+        )
+        .mount("/static", FileServer::from("static"))
+        .register("/api", catchers![catchers::default_catcher]);
+
+    if let Some(tokens) = tokens {
+        rocket = rocket.manage(tokens);
+    }
+
+    if let Some(oauth) = oauth {
+        rocket = rocket
+            .manage(oauth)
+            .mount("/", routes![login::login, login::callback, login::logout])
+            .register("/", catchers![catchers::login_redirect]);
+    }
+
+    rocket
+        .attach(Template::fairing())
+        .attach(metrics::RequestMetrics)
+}
+
+/// Generates a key to encrypt private cookies with, which are only valid
+/// until the next restart.
+fn random_secret_key() -> String {
+    let mut key = [0u8; 64];
+    rand::rng().fill_bytes(&mut key);
+    BASE64_STANDARD.encode(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::guards::SESSION_COOKIE;
+    use super::*;
+    use crate::testing;
+    use crate::users::accounts::Account;
+    use rocket::http::{Cookie, Header};
+    use rocket::local::asynchronous::Client;
+    use sha2::{Digest, Sha256};
+    use starsearch_sdk::models::JobState;
+    use tempfile::TempDir;
+    use tokio_cron_scheduler::JobScheduler;
+
+    const READ_TOKEN: &str = "reader";
+    const ADMIN_TOKEN: &str = "administrator";
+
+    /// Serves the stars of the configured user `tester` and of `alice`, who
+    /// logged in via GitHub, with API tokens required.
+    async fn client(dir: &TempDir) -> Client {
+        // Templates and static files are served from the workspace.
+        std::env::set_current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/..")).unwrap();
+
+        let hash = |token: &str| format!("{:x}", Sha256::digest(token));
+        let mut cfg = testing::config(dir.path());
+        cfg.github_client_id = Some("client".into());
+        cfg.github_client_secret = Some("secret".into());
+        cfg.api_tokens = Some(format!(
+            "read:{},admin:{}",
+            hash(READ_TOKEN),
+            hash(ADMIN_TOKEN)
+        ));
+
+        let oauth = OAuth::from_config(&cfg).unwrap();
+        let tokens = ApiTokens::from_config(&cfg).unwrap();
+        let users = Users::new(cfg, None, JobScheduler::new().await.unwrap()).unwrap();
+        for name in ["tester", "alice"] {
+            let user = match name {
+                "tester" => users.add(name).await.unwrap(),
+                _ => users
+                    .sign_in(Account {
+                        login: name.into(),
+                        token: "github-token".into(),
+                    })
+                    .await
+                    .unwrap(),
+            };
+            let repo = testing::repository(1, "owner/repo", 0);
+            user.db.insert_repos(&[repo]).await.unwrap();
+        }
+
+        let figment = Config::figment().merge((Config::SECRET_KEY, random_secret_key()));
+        Client::untracked(build(users, oauth, tokens).configure(figment))
+            .await
+            .unwrap()
+    }
+
+    fn session() -> Cookie<'static> {
+        Cookie::new(SESSION_COOKIE, "alice")
+    }
+
+    #[rocket::async_test]
+    async fn session_modifies_only_own_stars() {
+        let dir = TempDir::new().unwrap();
+        let client = client(&dir).await;
+        let annotate = |uri: &'static str| {
+            client
+                .put(uri)
+                .private_cookie(session())
+                .json(&Annotations {
+                    note: Some("note".into()),
+                    tags: vec![],
+                })
+                .dispatch()
+        };
+
+        let res = annotate("/api/repositories/1/annotations").await;
+        assert_eq!(res.status(), Status::Ok);
+        let res = annotate("/api/repositories/1/annotations?user=tester").await;
+        assert_eq!(res.status(), Status::Forbidden);
+
+        let res = client
+            .post("/api/refresh?user=tester")
+            .private_cookie(session())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Forbidden);
+
+        let res = client
+            .get("/api/repositories/1?user=tester")
+            .private_cookie(session())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn admin_token_modifies_configured_users() {
+        let dir = TempDir::new().unwrap();
+        let client = client(&dir).await;
+
+        let res = client
+            .put("/api/repositories/1/annotations?user=tester")
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {ADMIN_TOKEN}"),
+            ))
+            .json(&Annotations::default())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn browser_tokens_only_grant_read_access() {
+        let dir = TempDir::new().unwrap();
+        let client = client(&dir).await;
+
+        let uri = format!("/api/repositories/1?user=tester&token={ADMIN_TOKEN}");
+        let res = client.get(uri).dispatch().await;
+        assert_eq!(res.status(), Status::Unauthorized);
+        let uri = format!("/api/refresh?user=tester&token={ADMIN_TOKEN}");
+        let res = client.post(uri).dispatch().await;
+        assert_eq!(res.status(), Status::Unauthorized);
+
+        let uri = format!("/api/repositories/1?user=tester&token={READ_TOKEN}");
+        let res = client.get(uri).dispatch().await;
+        assert_eq!(res.status(), Status::Ok);
+        let cookie = res.cookies().get_private("api_token").unwrap();
+
+        let res = client
+            .get("/api/repositories/1?user=tester")
+            .private_cookie(cookie)
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn rendered_urls_omit_tokens() {
+        let dir = TempDir::new().unwrap();
+        let client = client(&dir).await;
+
+        for uri in [
+            "/?user=tester&query=repo",
+            "/feed.atom?user=tester&query=repo",
+        ] {
+            let mut req = client.get(format!("{uri}&token={READ_TOKEN}"));
+            req.inner_mut().set_host(Host::from(uri!("localhost")));
+            let res = req.dispatch().await;
+            assert_eq!(res.status(), Status::Ok);
+            let body = res.into_string().await.unwrap();
+            assert!(body.contains("/feed.atom?user"), "{body}");
+            assert!(!body.contains(READ_TOKEN), "{body}");
+        }
+    }
+
+    #[rocket::async_test]
+    async fn rejects_invalid_search_params() {
+        let dir = TempDir::new().unwrap();
+        let client = client(&dir).await;
+        let auth = Header::new("Authorization", format!("Bearer {READ_TOKEN}"));
+
+        for (params, code) in [
+            ("sort=bogus", "invalid_query"),
+            ("limit=abc", "invalid_query"),
+            ("mode=bogus", "invalid_query"),
+            ("starred_after=notadate", "invalid_filter"),
+            ("fork=maybe", "invalid_filter"),
+        ] {
+            let uri = format!("/api/search?user=tester&query=repo&{params}");
+            let res = client.get(uri).header(auth.clone()).dispatch().await;
+            assert_eq!(res.status(), Status::BadRequest, "{params}");
+            let body = res.into_string().await.unwrap();
+            assert!(body.contains(code), "{params}: {body}");
+            assert!(body.contains(params), "{params}: {body}");
+        }
+
+        let res = client
+            .get("/?user=tester&mode=bogus")
+            .header(auth.clone())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::BadRequest);
+
+        let mut req = client
+            .get("/feed.atom?user=tester&starred_after=notadate")
+            .header(auth.clone());
+        req.inner_mut().set_host(Host::from(uri!("localhost")));
+        let res = req.dispatch().await;
+        assert_eq!(res.status(), Status::BadRequest);
+        assert_eq!(res.content_type(), Some(ContentType::JSON));
+        let body = res.into_string().await.unwrap();
+        assert!(body.contains("invalid_filter"), "{body}");
+
+        let uri = "/api/search?user=tester&query=repo&sort=starred_at:asc&limit=5&fork=false";
+        let res = client.get(uri).header(auth).dispatch().await;
+        assert_eq!(res.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn failed_scrapes_keep_server_ready() {
+        let dir = TempDir::new().unwrap();
+        let client = client(&dir).await;
+        let auth = Header::new("Authorization", format!("Bearer {ADMIN_TOKEN}"));
+
+        // GitHub is unreachable, so the scrape fails. Refreshes are rate
+        // limited per client address.
+        let res = client
+            .post("/api/refresh?user=tester")
+            .header(auth.clone())
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Accepted);
+        let job: Job = res.into_json().await.unwrap();
+        loop {
+            let res = client
+                .get(format!("/api/jobs/{}", job.id))
+                .header(auth.clone())
+                .dispatch()
+                .await;
+            let job: Job = res.into_json().await.unwrap();
+            if job.state.is_finished() {
+                assert_eq!(job.state, JobState::Failed);
+                break;
+            }
+            rocket::tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+
+        let res = client.get("/readyz").dispatch().await;
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.into_string().await.unwrap();
+        assert!(body.contains(r#""last_scrape":"failed""#), "{body}");
+    }
+
+    #[rocket::async_test]
+    async fn metrics_leave_out_logged_in_users() {
+        let dir = TempDir::new().unwrap();
+        let client = client(&dir).await;
+
+        let res = client
+            .get("/metrics")
+            .header(Header::new("Authorization", format!("Bearer {READ_TOKEN}")))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.into_string().await.unwrap();
+        assert!(
+            body.contains("starsearch_index_documents{user=\"tester\"} 1"),
+            "{body}"
+        );
+        assert!(!body.contains("alice"), "{body}");
+    }
+}
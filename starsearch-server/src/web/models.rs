@@ -1,56 +1,361 @@
+use super::readme;
 use crate::db::errors::DatabaseError;
+use crate::db::MAX_TOTAL_HITS;
 use crate::scraper::errors::ScraperError;
-use rocket::http::Status;
+use chrono::{DateTime, Utc};
+use meilisearch_sdk::errors::{Error as MeiliError, ErrorCode};
+use rocket::form::{self, FromFormField, ValueField};
+use rocket::http::{RawStr, Status};
 use rocket::serde::json::Json;
 use serde::Serialize;
-use starsearch_sdk::models::Repository;
+use starsearch_sdk::errors::Error as SdkError;
+use starsearch_sdk::models::{
+    DateRange, FacetCount, Facets, Filter, HighlightedText, Repository, SearchMode, SearchOptions,
+    SearchResponse, Sort,
+};
 
 #[derive(Serialize)]
 pub struct RepositoryViewModel<'a> {
+    pub id: u32,
     pub name: &'a str,
     pub owner: &'a str,
     pub url: &'a str,
-    pub description: &'a Option<String>,
+    pub description: Option<Vec<TextPartViewModel<'a>>>,
+    /// Passage of the README matching the search query.
+    pub readme_snippet: Option<Vec<TextPartViewModel<'a>>>,
     pub language: &'a Option<String>,
     pub language_id: Option<String>,
     pub topics: &'a Option<Vec<String>>,
+    pub lists: Vec<ListViewModel<'a>>,
+    pub note: Option<&'a str>,
+    pub tags: &'a [String],
+    pub starred_by: &'a [String],
+    /// The user whose stars contain the repository, which is passed on in
+    /// links to the repository's pages. `None` for the default user.
+    pub user: Option<&'a str>,
 }
 
-impl<'a> From<&'a Repository> for RepositoryViewModel<'a> {
-    fn from(value: &'a Repository) -> Self {
+/// Star list of a repository.
+#[derive(Serialize)]
+pub struct ListViewModel<'a> {
+    pub name: &'a str,
+    /// Link to the repositories in the list.
+    pub uri: String,
+}
+
+/// Part of a text which is emphasized if it matched the search query.
+#[derive(Serialize)]
+pub struct TextPartViewModel<'a> {
+    pub text: &'a str,
+    pub matched: bool,
+}
+
+impl<'a> TextPartViewModel<'a> {
+    fn parts(text: &'a HighlightedText) -> Vec<Self> {
+        text.parts()
+            .into_iter()
+            .map(|(text, matched)| Self { text, matched })
+            .collect()
+    }
+}
+
+impl<'a> RepositoryViewModel<'a> {
+    /// Returns the view model of the given repository, of which the links
+    /// lead to the stars of the given user. It is `None` for the default
+    /// user.
+    pub fn new(value: &'a Repository, user: Option<&'a str>) -> Self {
+        let highlights = value.highlights.as_ref();
+
         Self {
+            id: value.id,
             name: &value.name,
-            description: &value.description,
+            description: highlights
+                .and_then(|h| h.description.as_ref())
+                .map(TextPartViewModel::parts)
+                .or_else(|| {
+                    let text = value.description.as_deref()?;
+                    Some(vec![TextPartViewModel {
+                        text,
+                        matched: false,
+                    }])
+                }),
+            readme_snippet: highlights
+                .and_then(|h| h.readme.as_ref())
+                .map(TextPartViewModel::parts),
             language: &value.language,
             language_id: value.language.as_ref().map(|v| v.to_lowercase()),
             owner: &value.owner.login,
             url: &value.html_url,
             topics: &value.topics,
+            lists: value
+                .lists
+                .iter()
+                .map(|name| {
+                    let mut uri = format!("/?list={}", RawStr::new(name).percent_encode());
+                    if let Some(user) = user {
+                        uri += &format!("&user={}", RawStr::new(user).percent_encode());
+                    }
+                    ListViewModel { name, uri }
+                })
+                .collect(),
+            note: value.annotations.as_ref().and_then(|a| a.note.as_deref()),
+            tags: value
+                .annotations
+                .as_ref()
+                .map(|a| a.tags.as_slice())
+                .unwrap_or_default(),
+            starred_by: &value.starred_by,
+            user,
         }
     }
 }
 
+/// A single repository with all of its stored details.
+#[derive(Serialize)]
+pub struct RepositoryDetailViewModel<'a> {
+    #[serde(flatten)]
+    pub repository: RepositoryViewModel<'a>,
+    pub full_name: &'a str,
+    pub license: Option<&'a str>,
+    pub fork: bool,
+    pub archived: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    pub starred_at: Option<String>,
+    pub scraped_at: Option<String>,
+    /// The README rendered from Markdown to sanitized HTML.
+    pub readme_html: Option<String>,
+    /// The README as plain text, if it is not written in Markdown.
+    pub readme_text: Option<&'a str>,
+    /// The tags as edited in the annotations form.
+    pub tags_text: String,
+}
+
+impl<'a> RepositoryDetailViewModel<'a> {
+    /// Returns the view model of the given repository, of which the links
+    /// lead to the stars of the given user as for [`RepositoryViewModel`].
+    pub fn new(value: &'a Repository, user: Option<&'a str>) -> Self {
+        let date = |date: &DateTime<Utc>| date.format("%Y-%m-%d").to_string();
+        let readme_html = readme::render(value);
+
+        Self {
+            repository: RepositoryViewModel::new(value, user),
+            full_name: &value.full_name,
+            license: value
+                .license
+                .as_ref()
+                .and_then(|l| l.name.as_deref().or(l.spdx_id.as_deref())),
+            fork: value.fork,
+            archived: value.archived,
+            created_at: date(&value.created_at),
+            updated_at: date(&value.updated_at),
+            starred_at: value.starred_at.as_ref().map(date),
+            scraped_at: value
+                .scraped_at
+                .map(|d| d.format("%Y-%m-%d %H:%M UTC").to_string()),
+            readme_text: match readme_html {
+                Some(_) => None,
+                None => value.readme_content.as_deref(),
+            },
+            readme_html,
+            tags_text: value
+                .annotations
+                .as_ref()
+                .map(|a| a.tags.join(", "))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Entry of the Atom and RSS feeds of starred repositories.
+#[derive(Serialize)]
+pub struct FeedEntryViewModel<'a> {
+    pub full_name: &'a str,
+    pub url: &'a str,
+    pub description: Option<&'a str>,
+    pub language: Option<&'a str>,
+    pub topics: &'a [String],
+    pub readme_excerpt: Option<String>,
+    pub starred_by: &'a [String],
+    /// Time the repository has been starred or, if unknown, scraped,
+    /// formatted as required by the feed format.
+    pub date: String,
+}
+
+/// Search suggestions in the OpenSearch Suggestions format, which is an array
+/// of the query, the completions, their descriptions and their URLs.
+#[derive(Serialize)]
+pub struct Suggestions(
+    pub String,
+    pub Vec<String>,
+    pub Vec<String>,
+    pub Vec<String>,
+);
+
+#[derive(Serialize)]
+pub struct PaginationViewModel {
+    pub from: usize,
+    pub to: usize,
+    pub total: usize,
+    pub previous: Option<String>,
+    pub next: Option<String>,
+}
+
+impl PaginationViewModel {
+    /// Creates the pagination of the given response. `uri` returns the link
+    /// to the page starting at the passed offset.
+    pub fn new(res: &SearchResponse, uri: impl Fn(usize) -> String) -> Option<Self> {
+        if res.hits.is_empty() {
+            return None;
+        }
+
+        let to = res.offset + res.hits.len();
+        Some(Self {
+            from: res.offset + 1,
+            to,
+            total: res.total,
+            previous: (res.offset > 0).then(|| uri(res.offset.saturating_sub(res.limit))),
+            // Hits beyond the maximum offset are not reachable by paging.
+            next: (to < res.total.min(MAX_TOTAL_HITS)).then(|| uri(to)),
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct FacetViewModel<'a> {
+    pub title: &'static str,
+    pub values: Vec<FacetValueViewModel<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct FacetValueViewModel<'a> {
+    pub value: &'a str,
+    pub count: usize,
+    /// Whether the results are already filtered by this value.
+    pub active: bool,
+    /// Link which adds or, if active, removes the filter.
+    pub uri: String,
+}
+
+impl<'a> FacetViewModel<'a> {
+    /// Creates the sidebar facets of the given distribution. `uri` returns
+    /// the link toggling the filter of the passed query parameter and value.
+    pub fn new(
+        facets: &'a Facets,
+        filter: &Filter,
+        uri: impl Fn(&str, &str, bool) -> String,
+    ) -> Vec<Self> {
+        // Filter values of each facet the results are currently filtered by.
+        let groups: [(_, _, &[FacetCount], &[String]); 5] = [
+            (
+                "Languages",
+                "language",
+                &facets.language,
+                filter.language.as_slice(),
+            ),
+            ("Topics", "topic", &facets.topics, &filter.topics_all),
+            (
+                "Licenses",
+                "license",
+                &facets.license,
+                filter.license.as_slice(),
+            ),
+            ("Owners", "owner", &facets.owner, filter.owner.as_slice()),
+            ("Lists", "list", &facets.lists, &filter.lists),
+        ];
+
+        groups
+            .into_iter()
+            .filter(|(_, _, values, _)| !values.is_empty())
+            .map(|(title, param, values, active)| Self {
+                title,
+                values: values
+                    .iter()
+                    .map(|facet| {
+                        let active = active.iter().any(|v| v.eq_ignore_ascii_case(&facet.value));
+                        FacetValueViewModel {
+                            value: &facet.value,
+                            count: facet.count,
+                            active,
+                            uri: uri(param, &facet.value, active),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Outcome of the readiness check of the server.
+#[derive(Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    /// Either `ok` or `unavailable` if the backend of any user is not
+    /// reachable or its indexes are missing or misconfigured.
+    pub search_backend: &'static str,
+    /// Either `succeeded`, `failed` if the last scrape of any user has
+    /// failed, or `none` if no scrape has finished yet.
+    pub last_scrape: &'static str,
+}
+
+/// Body of error responses, of which the code can be matched by clients and
+/// the message is meant for humans.
 #[derive(Serialize)]
 pub struct Error {
+    pub code: &'static str,
     pub message: String,
 }
 
-impl<E: ToString> From<E> for Error {
-    fn from(value: E) -> Self {
+impl Error {
+    pub fn new(code: &'static str, message: impl ToString) -> Self {
         Self {
-            message: value.to_string(),
+            code,
+            message: message.to_string(),
         }
     }
+
+    /// Describes why the search parameters could not be parsed.
+    pub fn invalid_search(err: SdkError) -> Self {
+        let code = match err {
+            SdkError::InvalidFilter(_) => "invalid_filter",
+            _ => "invalid_query",
+        };
+        Self::new(code, err)
+    }
+}
+
+/// Returns the response status and error code of the given database error.
+/// Rejected queries and filters are the fault of the client, while failures
+/// to reach the search backend are assumed to be temporary.
+pub fn classify(err: &DatabaseError) -> (Status, &'static str) {
+    match err {
+        DatabaseError::SemanticSearchDisabled => (Status::BadRequest, "semantic_search_disabled"),
+        DatabaseError::MeiliError(err) => match err {
+            MeiliError::Meilisearch(err) => match err.error_code {
+                ErrorCode::InvalidSearchFilter => (Status::BadRequest, "invalid_filter"),
+                ErrorCode::InvalidSearchQ
+                | ErrorCode::InvalidSearchSort
+                | ErrorCode::InvalidSearchOffset
+                | ErrorCode::InvalidSearchLimit
+                | ErrorCode::InvalidSearchFacets => (Status::BadRequest, "invalid_query"),
+                ErrorCode::IndexNotFound => (Status::ServiceUnavailable, "backend_unavailable"),
+                _ => (Status::InternalServerError, "internal_error"),
+            },
+            MeiliError::HttpError(_)
+            | MeiliError::Timeout
+            | MeiliError::MeilisearchCommunication(_) => {
+                (Status::ServiceUnavailable, "backend_unavailable")
+            }
+            _ => (Status::InternalServerError, "internal_error"),
+        },
+        _ => (Status::InternalServerError, "internal_error"),
+    }
 }
 
 impl From<DatabaseError> for (Status, Json<Error>) {
     fn from(value: DatabaseError) -> Self {
-        (
-            Status::InternalServerError,
-            Json(Error {
-                message: value.to_string(),
-            }),
-        )
+        let (status, code) = classify(&value);
+        (status, Json(Error::new(code, value)))
     }
 }
 
@@ -58,9 +363,292 @@ impl From<ScraperError> for (Status, Json<Error>) {
     fn from(value: ScraperError) -> Self {
         (
             Status::InternalServerError,
-            Json(Error {
-                message: value.to_string(),
-            }),
+            Json(Error::new("internal_error", value)),
         )
     }
 }
+
+const DEFAULT_LIMIT: usize = 30;
+
+/// Maximum number of hits returned at once. Larger limits are clamped.
+pub const MAX_LIMIT: usize = 100;
+
+/// Paging, filter and sort query parameters of searches and listings.
+#[derive(FromForm)]
+pub struct SearchParams<'r> {
+    pub limit: Param<usize>,
+    pub offset: Param<usize>,
+    /// 1-based page number, which is ignored when an offset is given.
+    pub page: Param<usize>,
+    pub language: Option<&'r str>,
+    pub topic: Vec<&'r str>,
+    pub topic_any: Vec<&'r str>,
+    pub license: Option<&'r str>,
+    pub owner: Option<&'r str>,
+    pub list: Vec<&'r str>,
+    pub fork: Param<bool>,
+    pub archived: Param<bool>,
+    pub created_after: Param<DateParam>,
+    pub created_before: Param<EndDateParam>,
+    pub updated_after: Param<DateParam>,
+    pub updated_before: Param<EndDateParam>,
+    pub starred_after: Param<DateParam>,
+    pub starred_before: Param<EndDateParam>,
+    pub sort: Param<SortParam>,
+    pub mode: Param<SearchModeParam>,
+    /// Whether to include the facet distribution in the response.
+    pub facets: Param<bool>,
+    /// Whether to search the stars of all users.
+    pub team: Param<bool>,
+}
+
+impl SearchParams<'_> {
+    /// Returns the number of hits per page, which is at most [`MAX_LIMIT`].
+    pub fn limit(&self) -> usize {
+        self.limit
+            .value()
+            .copied()
+            .unwrap_or(DEFAULT_LIMIT)
+            .min(MAX_LIMIT)
+    }
+
+    /// Returns the number of hits to skip, which is at most the number of
+    /// hits reachable by paging.
+    pub fn offset(&self) -> usize {
+        self.offset
+            .value()
+            .copied()
+            .or_else(|| {
+                self.page
+                    .value()
+                    .map(|page| page.saturating_sub(1).saturating_mul(self.limit()))
+            })
+            .unwrap_or_default()
+            .min(MAX_TOTAL_HITS)
+    }
+
+    /// Returns the first invalid parameter, if any.
+    fn validate(&self) -> starsearch_sdk::errors::Result<()> {
+        let invalid = [
+            self.limit.error(),
+            self.offset.error(),
+            self.page.error(),
+            self.sort.error(),
+            self.mode.error(),
+            self.facets.error(),
+            self.team.error(),
+        ];
+        if let Some(err) = invalid.into_iter().flatten().next() {
+            return Err(SdkError::InvalidParameter(err.into()));
+        }
+
+        let invalid_filters = [
+            self.fork.error(),
+            self.archived.error(),
+            self.created_after.error(),
+            self.created_before.error(),
+            self.updated_after.error(),
+            self.updated_before.error(),
+            self.starred_after.error(),
+            self.starred_before.error(),
+        ];
+        if let Some(err) = invalid_filters.into_iter().flatten().next() {
+            return Err(SdkError::InvalidFilter(err.into()));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the free text of the given query and the search options
+    /// combined from the query parameters and the inline filters of the
+    /// query.
+    pub fn options(&self, query: &str) -> starsearch_sdk::errors::Result<(String, SearchOptions)> {
+        self.validate()?;
+        let (text, inline) = starsearch_sdk::query::parse(query)?;
+
+        let range = |after: &Param<DateParam>, before: &Param<EndDateParam>| DateRange {
+            after: after.value().map(|v| v.0),
+            before: before.value().map(|v| v.0),
+        };
+
+        let mut filter = Filter {
+            language: self.language.map(Into::into),
+            topics_all: self.topic.iter().map(|&t| t.into()).collect(),
+            topics_any: self.topic_any.iter().map(|&t| t.into()).collect(),
+            license: self.license.map(Into::into),
+            owner: self.owner.map(Into::into),
+            lists: self.list.iter().map(|&l| l.into()).collect(),
+            fork: self.fork.value().copied(),
+            archived: self.archived.value().copied(),
+            created: range(&self.created_after, &self.created_before),
+            updated: range(&self.updated_after, &self.updated_before),
+            starred: range(&self.starred_after, &self.starred_before),
+        };
+        filter.merge(inline);
+
+        let opts = SearchOptions {
+            filter,
+            sort: self.sort.value().map(|v| v.0),
+            mode: self.mode.value().map(|v| v.0).unwrap_or_default(),
+            facets: self.facets.value().copied().unwrap_or_default(),
+            team: self.team.value().copied().unwrap_or_default(),
+        };
+
+        Ok((text, opts))
+    }
+}
+
+/// Optional query parameter. Unlike `Option`, which silently ignores values
+/// failing to parse, it keeps them to be rejected by
+/// [`SearchParams::options`].
+pub struct Param<T>(Result<Option<T>, String>);
+
+impl<T> Param<T> {
+    /// Returns the value, if given and valid.
+    pub fn value(&self) -> Option<&T> {
+        self.0.as_ref().ok().and_then(Option::as_ref)
+    }
+
+    /// Returns the invalid parameter as `name=value`, if any.
+    pub fn error(&self) -> Option<&str> {
+        self.0.as_ref().err().map(String::as_str)
+    }
+}
+
+impl<'v, T: FromFormField<'v>> FromFormField<'v> for Param<T> {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        let invalid = format!("{}={}", field.name, field.value);
+        Ok(Self(T::from_value(field).map(Some).map_err(|_| invalid)))
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(Ok(None)))
+    }
+}
+
+/// Date query parameter accepting either RFC 3339 timestamps or plain
+/// `YYYY-MM-DD` dates, which are interpreted as midnight UTC.
+pub struct DateParam(pub DateTime<Utc>);
+
+impl<'v> FromFormField<'v> for DateParam {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        let date = starsearch_sdk::query::parse_date(field.value)
+            .ok_or_else(|| form::Error::validation("invalid date"))?;
+        Ok(Self(date))
+    }
+}
+
+/// Upper bound of a date range accepting the same values as [`DateParam`],
+/// except that plain dates include the whole day.
+pub struct EndDateParam(pub DateTime<Utc>);
+
+impl<'v> FromFormField<'v> for EndDateParam {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        let date = starsearch_sdk::query::parse_end_date(field.value)
+            .ok_or_else(|| form::Error::validation("invalid date"))?;
+        Ok(Self(date))
+    }
+}
+
+pub struct SortParam(pub Sort);
+
+impl<'v> FromFormField<'v> for SortParam {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        let sort = field
+            .value
+            .parse()
+            .map_err(|err: starsearch_sdk::errors::Error| {
+                form::Error::validation(err.to_string())
+            })?;
+        Ok(Self(sort))
+    }
+}
+
+pub struct SearchModeParam(pub SearchMode);
+
+impl<'v> FromFormField<'v> for SearchModeParam {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        let mode = field
+            .value
+            .parse()
+            .map_err(|err: starsearch_sdk::errors::Error| {
+                form::Error::validation(err.to_string())
+            })?;
+        Ok(Self(mode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn list_links_keep_user() {
+        let mut repo = testing::repository(1, "owner/repo", 0);
+        repo.lists = vec!["Dev Tools".into()];
+
+        let model = RepositoryViewModel::new(&repo, None);
+        assert_eq!(model.lists[0].uri, "/?list=Dev%20Tools");
+        let model = RepositoryViewModel::new(&repo, Some("alice"));
+        assert_eq!(model.lists[0].uri, "/?list=Dev%20Tools&user=alice");
+        assert_eq!(model.user, Some("alice"));
+    }
+
+    /// Returns a response holding `hits` of `total` repositories from the
+    /// given offset.
+    fn response(offset: usize, hits: usize, total: usize) -> SearchResponse {
+        SearchResponse {
+            hits: (0..hits)
+                .map(|i| testing::repository(i as u32, "owner/repo", 0))
+                .collect(),
+            total,
+            offset,
+            limit: 10,
+            facets: None,
+        }
+    }
+
+    fn pagination(offset: usize, hits: usize, total: usize) -> Option<PaginationViewModel> {
+        PaginationViewModel::new(&response(offset, hits, total), |offset| {
+            format!("?offset={offset}")
+        })
+    }
+
+    #[test]
+    fn pagination_of_first_and_middle_pages() {
+        let page = pagination(0, 10, 25).unwrap();
+        assert_eq!((page.from, page.to, page.total), (1, 10, 25));
+        assert_eq!(page.previous, None);
+        assert_eq!(page.next.as_deref(), Some("?offset=10"));
+
+        let page = pagination(10, 10, 25).unwrap();
+        assert_eq!((page.from, page.to), (11, 20));
+        assert_eq!(page.previous.as_deref(), Some("?offset=0"));
+        assert_eq!(page.next.as_deref(), Some("?offset=20"));
+
+        // Pages at unaligned offsets link back to the start.
+        let page = pagination(5, 10, 25).unwrap();
+        assert_eq!(page.previous.as_deref(), Some("?offset=0"));
+    }
+
+    #[test]
+    fn pagination_of_last_page() {
+        let page = pagination(20, 5, 25).unwrap();
+        assert_eq!((page.from, page.to), (21, 25));
+        assert_eq!(page.previous.as_deref(), Some("?offset=10"));
+        assert_eq!(page.next, None);
+
+        // Hits beyond the maximum offset can't be paged to.
+        let page = pagination(MAX_TOTAL_HITS - 10, 10, MAX_TOTAL_HITS * 2).unwrap();
+        assert_eq!(page.to, MAX_TOTAL_HITS);
+        assert_eq!(page.total, MAX_TOTAL_HITS * 2);
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn pagination_past_total() {
+        assert!(pagination(30, 0, 25).is_none());
+        assert!(pagination(0, 0, 0).is_none());
+    }
+}
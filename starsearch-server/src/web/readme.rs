@@ -0,0 +1,220 @@
+use ammonia::{Builder, Url, UrlRelative, UrlRelativeEvaluate};
+use pulldown_cmark::{html, Event, Options, Parser, Tag, TagEnd};
+use starsearch_sdk::models::Repository;
+use std::borrow::Cow;
+
+/// File extensions of READMEs which are rendered as Markdown. All other
+/// READMEs are shown as plain text.
+const MARKDOWN_EXTENSIONS: [&str; 4] = ["md", "markdown", "mdown", "mkd"];
+
+/// Branch used in links to files on GitHub if the default branch of the
+/// repository is unknown.
+const FALLBACK_BRANCH: &str = "HEAD";
+
+/// Renders the stored README of the given repository as sanitized HTML.
+/// Returns `None` if the repository has no README or it is not written in
+/// Markdown.
+///
+/// As on GitHub, relative links point to the linked files in the repository
+/// and relative images are loaded from there.
+pub fn render(repo: &Repository) -> Option<String> {
+    let content = repo.readme_content.as_deref()?;
+    let path = repo.readme_path.as_deref().unwrap_or("README.md");
+    if !is_markdown(path) {
+        return None;
+    }
+
+    let branch = repo.default_branch.as_deref().unwrap_or(FALLBACK_BRANCH);
+    let dir = path
+        .rsplit_once('/')
+        .map(|(dir, _)| format!("{dir}/"))
+        .unwrap_or_default();
+    let base = |kind: &str| Url::parse(&format!("{}/{kind}/{branch}/{dir}", repo.html_url)).ok();
+    let (blob_base, raw_base) = (base("blob")?, base("raw")?);
+
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_GFM;
+    let events = Parser::new_ext(content, options).map(|event| match event {
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: match resolve(&blob_base, &dest_url) {
+                Some(url) => url.into_owned().into(),
+                None => dest_url,
+            },
+            title,
+            id,
+        }),
+        event => event,
+    });
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events);
+
+    // Remaining relative URLs stem from images and inline HTML.
+    let html = Builder::default()
+        .url_relative(UrlRelative::Custom(Box::new(RawFileUrl(raw_base))))
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .clean(&unsafe_html)
+        .to_string();
+
+    Some(html)
+}
+
+/// Returns the beginning of the README of the given repository as plain text
+/// of up to `max_chars` characters, cut off at a word boundary.
+pub fn excerpt(repo: &Repository, max_chars: usize) -> Option<String> {
+    let content = repo.readme_content.as_deref()?;
+    let path = repo.readme_path.as_deref().unwrap_or("README.md");
+
+    let text = if is_markdown(path) {
+        let mut text = String::new();
+        for event in Parser::new(content) {
+            match event {
+                Event::Text(t) | Event::Code(t) => text.push_str(&t),
+                // Inline markup is part of the surrounding words.
+                Event::End(
+                    TagEnd::Emphasis
+                    | TagEnd::Strong
+                    | TagEnd::Strikethrough
+                    | TagEnd::Link
+                    | TagEnd::Image,
+                ) => {}
+                Event::SoftBreak | Event::HardBreak | Event::End(_) => text.push(' '),
+                _ => {}
+            }
+        }
+        text
+    } else {
+        content.to_string()
+    };
+
+    let words: Vec<_> = text.split_whitespace().collect();
+    let mut excerpt = String::new();
+    for word in &words {
+        if excerpt.chars().count() + word.chars().count() + 1 > max_chars {
+            excerpt.push('…');
+            break;
+        }
+        if !excerpt.is_empty() {
+            excerpt.push(' ');
+        }
+        excerpt.push_str(word);
+    }
+
+    (!excerpt.is_empty()).then_some(excerpt)
+}
+
+fn is_markdown(path: &str) -> bool {
+    path.rsplit_once('.')
+        .is_some_and(|(_, ext)| MARKDOWN_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Resolves the given URL relative to the base. Absolute URLs and fragments
+/// are returned as they are.
+fn resolve<'a>(base: &Url, url: &'a str) -> Option<Cow<'a, str>> {
+    if url.starts_with('#') || Url::parse(url).is_ok() {
+        return Some(url.into());
+    }
+
+    base.join(url).ok().map(|url| url.to_string().into())
+}
+
+/// Resolves relative URLs to the raw files in the repository.
+struct RawFileUrl(Url);
+
+impl UrlRelativeEvaluate<'_> for RawFileUrl {
+    fn evaluate<'url>(&self, url: &'url str) -> Option<Cow<'url, str>> {
+        resolve(&self.0, url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn repo(path: &str, content: &str) -> Repository {
+        serde_json::from_value(json!({
+            "id": 1,
+            "name": "repo",
+            "full_name": "owner/repo",
+            "owner": { "id": 1, "login": "owner" },
+            "description": null,
+            "fork": false,
+            "url": "https://api.github.com/repos/owner/repo",
+            "html_url": "https://github.com/owner/repo",
+            "created_at": "2020-01-01T00:00:00Z",
+            "updated_at": "2020-01-01T00:00:00Z",
+            "language": null,
+            "license": null,
+            "topics": null,
+            "default_branch": "main",
+            "readme_content": content,
+            "readme_path": path,
+            "disabled": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn excerpt_strips_markdown() {
+        let repo = repo(
+            "README.md",
+            "# Title\n\nSome *emph*asized `code`\nand [a link](x).",
+        );
+        assert_eq!(
+            excerpt(&repo, 100).as_deref(),
+            Some("Title Some emphasized code and a link.")
+        );
+    }
+
+    #[test]
+    fn excerpt_cuts_at_word_boundary() {
+        let repo = repo("README.md", "one two three four");
+        assert_eq!(excerpt(&repo, 10).as_deref(), Some("one two…"));
+    }
+
+    #[test]
+    fn excerpt_keeps_plain_text() {
+        let repo = repo("README.txt", "# not   a\n*heading*");
+        assert_eq!(excerpt(&repo, 100).as_deref(), Some("# not a *heading*"));
+    }
+
+    #[test]
+    fn excerpt_of_empty_readme() {
+        assert_eq!(excerpt(&repo("README.md", "  \n"), 100), None);
+    }
+
+    #[test]
+    fn render_resolves_relative_urls() {
+        let repo = repo(
+            "docs/README.md",
+            "[guide](guide.md) [top](#top) ![logo](img/logo.png)",
+        );
+        let html = render(&repo).unwrap();
+        assert!(html.contains(r#"href="https://github.com/owner/repo/blob/main/docs/guide.md""#));
+        assert!(html.contains(r##"href="#top""##));
+        assert!(html.contains(r#"src="https://github.com/owner/repo/raw/main/docs/img/logo.png""#));
+    }
+
+    #[test]
+    fn render_sanitizes_html() {
+        let repo = repo("README.md", "<script>alert(1)</script><b>bold</b>");
+        let html = render(&repo).unwrap();
+        assert!(!html.contains("script"));
+        assert!(html.contains("<b>bold</b>"));
+    }
+
+    #[test]
+    fn render_skips_plain_text() {
+        assert_eq!(render(&repo("README.rst", "text")), None);
+    }
+}
@@ -1,16 +1,23 @@
 const inputQuery = document.getElementById("input-query");
 const inputLanguage = document.getElementById("input-language");
+const inputMode = document.getElementById("input-mode");
+const inputUser = document.getElementById("input-user");
 const searchButton = document.getElementById("button-search");
 
 const search = () => {
   const limit = null;
   const query = inputQuery.value;
   const language = inputLanguage.value.length > 0 ? inputLanguage.value : undefined;
+  const mode = inputMode && inputMode.value !== "keyword" ? inputMode.value : undefined;
+  const user = inputUser ? inputUser.value : undefined;
 
   const params = new URLSearchParams();
   if (query) params.append("query", query);
   if (limit) params.append("limit", limit);
   if (language) params.append("language", language);
+  if (mode) params.append("mode", mode);
+  if (user === "*") params.append("team", true);
+  else if (user && user !== inputUser.options[0].value) params.append("user", user);
 
   window.location.assign("/?" + params.toString());
 };
@@ -19,6 +26,18 @@ searchButton.onclick = () => {
   search();
 };
 
+if (inputMode) {
+  inputMode.onchange = () => {
+    if (inputQuery.value) search();
+  };
+}
+
+if (inputUser) {
+  inputUser.onchange = () => {
+    search();
+  };
+}
+
 inputQuery.onkeyup = (event) => {
   switch (event.key) {
     case "Enter":
@@ -0,0 +1,41 @@
+const inputNote = document.getElementById("input-note");
+const inputTags = document.getElementById("input-tags");
+const saveButton = document.getElementById("button-save");
+const annotationsStatus = document.getElementById("annotations-status");
+
+const save = async () => {
+  const annotations = {
+    note: inputNote.value,
+    tags: inputTags.value.split(","),
+  };
+
+  const params = new URLSearchParams();
+  if (saveButton.dataset.user) params.append("user", saveButton.dataset.user);
+
+  saveButton.disabled = true;
+  try {
+    const res = await fetch(`/api/repositories/${saveButton.dataset.id}/annotations?${params}`, {
+      method: "PUT",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify(annotations),
+    });
+    if (!res.ok) throw new Error(res.statusText);
+
+    const saved = await res.json();
+    inputNote.value = saved.note ?? "";
+    inputTags.value = (saved.tags ?? []).join(", ");
+    annotationsStatus.innerText = "Saved.";
+  } catch (err) {
+    annotationsStatus.innerText = `Saving failed: ${err.message}`;
+  } finally {
+    saveButton.disabled = false;
+  }
+};
+
+saveButton.onclick = () => {
+  save();
+};
+
+inputTags.onkeyup = (event) => {
+  if (event.key === "Enter") save();
+};